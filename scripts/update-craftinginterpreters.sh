@@ -0,0 +1,55 @@
+#!/bin/sh
+# replaces tests/craftinginterpreters with test/ from the craftinginterpreters repository at
+# the given commit, then rebuilds known_failures.txt from a run against it. entries that were
+# already on the list keep their tag, new ones are marked [untagged] until someone tags them
+# with a difference from the README, or fixes the interpreter.
+#
+#   scripts/update-craftinginterpreters.sh <commit>
+
+set -eu
+
+if [ $# -ne 1 ]; then
+  echo "usage: $0 <commit>" >&2
+  exit 64
+fi
+
+commit=$1
+root=$(cd "$(dirname "$0")/.." && pwd)
+suite=$root/tests/craftinginterpreters
+upstream=${CRAFTINGINTERPRETERS_REPO:-https://github.com/munificent/craftinginterpreters}
+
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+git -C "$work" init -q checkout
+git -C "$work/checkout" fetch -q --depth 1 "$upstream" "$commit"
+git -C "$work/checkout" checkout -q FETCH_HEAD
+commit=$(git -C "$work/checkout" rev-parse HEAD)
+
+# the two files that aren't upstream's
+cp "$suite/known_failures.txt" "$suite/skipped.txt" "$work"
+
+rm -rf "$suite"
+cp -R "$work/checkout/test" "$suite"
+cp "$work/skipped.txt" "$suite"
+
+# run with nothing known to fail, so every failure shows up
+: > "$suite/known_failures.txt"
+cargo run -q --manifest-path "$root/Cargo.toml" -- test "$suite" > "$work/run.txt" || true
+
+{
+  grep '^#' "$work/known_failures.txt"
+  echo
+  sed -n "s|^FAIL $suite/||p" "$work/run.txt" | sort | while read -r test; do
+    tagged=$(grep "^$test " "$work/known_failures.txt" || true)
+    echo "${tagged:-$test [untagged]}"
+  done
+} > "$suite/known_failures.txt"
+
+# the README says which commit the suite was copied from
+sed -i.bak "s|^  - Copied from upstream at commit .*|  - Copied from upstream at commit \`$commit\`.|" \
+  "$root/README.md"
+rm "$root/README.md.bak"
+
+echo "copied test/ at $commit"
+(grep -c '\[untagged\]' "$suite/known_failures.txt" || true) | sed 's/$/ new failures to tag in known_failures.txt/'
@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::*;
+use crate::iterator::*;
+use crate::map::*;
+use crate::native::*;
+use crate::value::*;
+
+//the natives defined in the global scope of every program. they go through the same
+//registry as natives defined by host code
+pub fn define_builtins(interpreter: &mut Interpreter) {
+    interpreter.define_native("clock", Arity::Fixed(0), clock);
+    interpreter.define_native("len", Arity::Fixed(1), len);
+    interpreter.define_native("keys", Arity::Fixed(1), keys);
+    interpreter.define_native("has", Arity::Fixed(2), has);
+    interpreter.define_native("remove", Arity::Fixed(2), remove);
+    interpreter.define_native("push", Arity::Fixed(2), push);
+    interpreter.define_native("pop", Arity::Fixed(1), pop);
+    //range(end), range(start, end) or range(start, end, step)
+    interpreter.define_native("range", Arity::Range(1, 3), range);
+}
+
+//seconds since the unix epoch
+fn clock(_arguments: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| err.to_string())?;
+
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+fn len(arguments: &[Value]) -> Result<Value, String> {
+    let len = match &arguments[0] {
+        Value::List(elements) => elements.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        Value::String(s) => s.chars().count(),
+        Value::Range(range) => range.len(),
+        _ => return Err("Can only get the length of lists, maps, strings and ranges.".to_string()),
+    };
+
+    Ok(Value::Number(len as f64))
+}
+
+//in insertion order
+fn keys(arguments: &[Value]) -> Result<Value, String> {
+    let map = map_argument(arguments, "keys")?;
+    let keys = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+
+    Ok(Value::List(Rc::new(RefCell::new(keys))))
+}
+
+fn has(arguments: &[Value]) -> Result<Value, String> {
+    let map = map_argument(arguments, "has")?;
+    let key = map_key(&arguments[1])?;
+
+    let has = map.borrow().contains_key(&key);
+    Ok(Value::Bool(has))
+}
+
+//gives back the removed value
+fn remove(arguments: &[Value]) -> Result<Value, String> {
+    let map = map_argument(arguments, "remove")?;
+    let key = map_key(&arguments[1])?;
+
+    let removed = map.borrow_mut().remove(&key);
+    removed.ok_or_else(|| missing_key(&key))
+}
+
+//adds the value to the end of the list
+fn push(arguments: &[Value]) -> Result<Value, String> {
+    let list = list_argument(arguments, "push")?;
+    list.borrow_mut().push(arguments[1].clone());
+
+    Ok(Value::Nil)
+}
+
+//takes the last value off the end of the list and gives it back
+fn pop(arguments: &[Value]) -> Result<Value, String> {
+    let list = list_argument(arguments, "pop")?;
+    let popped = list.borrow_mut().pop();
+
+    popped.ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+fn range(arguments: &[Value]) -> Result<Value, String> {
+    let mut numbers = vec![];
+
+    for argument in arguments {
+        match argument {
+            Value::Number(n) => numbers.push(*n),
+            _ => return Err("Range bounds must be numbers.".to_string()),
+        }
+    }
+
+    let (start, end, step) = match numbers[..] {
+        [end] => (0.0, end, 1.0),
+        [start, end] => (start, end, 1.0),
+        [start, end, step] => (start, end, step),
+        _ => unreachable!("the arity has already been checked"),
+    };
+
+    if step == 0.0 || step.is_nan() {
+        return Err("Range step can't be zero.".to_string());
+    }
+
+    Ok(Value::Range(LoxRange { start, end, step }))
+}
+
+fn list_argument(arguments: &[Value], name: &str) -> Result<Rc<RefCell<Vec<Value>>>, String> {
+    match &arguments[0] {
+        Value::List(list) => Ok(Rc::clone(list)),
+        _ => Err(format!("First argument to '{name}' must be a list.")),
+    }
+}
+
+fn map_argument(arguments: &[Value], name: &str) -> Result<Rc<RefCell<LoxMap>>, String> {
+    match &arguments[0] {
+        Value::Map(map) => Ok(Rc::clone(map)),
+        _ => Err(format!("First argument to '{name}' must be a map.")),
+    }
+}
+
+pub fn map_key(value: &Value) -> Result<MapKey, String> {
+    MapKey::from_value(value)
+        .ok_or_else(|| "Map keys must be strings, numbers, booleans or nil.".to_string())
+}
+
+pub fn missing_key(key: &MapKey) -> String {
+    format!("Key '{}' is not in the map.", key.to_value())
+}
@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::function::*;
+use crate::symbol::*;
+use crate::value::*;
+
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: Rc<str>,
+    pub superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: Rc<str>,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Symbol, Rc<LoxFunction>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    //looks in the superclass chain when the class itself doesn't have it
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+
+    //true for the class itself too
+    pub fn is_subclass_of(self: &Rc<Self>, other: &Rc<LoxClass>) -> bool {
+        let mut class = Some(self);
+
+        while let Some(current) = class {
+            if Rc::ptr_eq(current, other) {
+                return true;
+            }
+
+            class = current.superclass.as_ref();
+        }
+
+        false
+    }
+
+    //calling a class takes whatever its `init` takes
+    pub fn arity(&self) -> usize {
+        self.find_method(Symbol::intern("init"))
+            .map_or(0, |init| init.arity())
+    }
+}
+
+impl fmt::Display for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: RefCell<HashMap<Symbol, Value>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    //fields shadow methods. methods come back bound to the instance
+    pub fn get(self: &Rc<Self>, name: Symbol) -> Option<Value> {
+        if let Some(value) = self.fields.borrow().get(&name) {
+            return Some(value.clone());
+        }
+
+        self.class
+            .find_method(name)
+            .map(|method| Value::Function(Rc::new(method.bind(Rc::clone(self)))))
+    }
+
+    pub fn field(&self, name: Symbol) -> Option<Value> {
+        self.fields.borrow().get(&name).cloned()
+    }
+
+    pub fn set(&self, name: Symbol, value: Value) {
+        self.fields.borrow_mut().insert(name, value);
+    }
+}
+
+//fields can hold the instance itself, so printing them could recurse forever
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxInstance({self})")
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -0,0 +1,188 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::expr::*;
+use crate::interpreter::*;
+use crate::symbol::*;
+use crate::token::*;
+use crate::value::*;
+
+//one scope of variables. names are interned symbols so lookups hash a u32, not a string
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    //redefining an existing name is allowed, it just replaces the old value
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        self.lookup(Self::symbol(name))
+            .ok_or_else(|| Self::undefined(name))
+    }
+
+    //looks a name up where the parser resolved it to. a local is only looked for in the scope
+    //it was declared in, a global in the globals and then the builtins behind them
+    pub fn get_at(&self, depth: Depth, name: &Token) -> Result<Value, RuntimeError> {
+        match depth {
+            Depth::Local(0) => self
+                .values
+                .get(&Self::symbol(name))
+                .cloned()
+                .ok_or_else(|| Self::undefined(name)),
+            Depth::Global(0) => self.get(name),
+            _ => self.enclosing(name)?.borrow().get_at(depth.outer(), name),
+        }
+    }
+
+    pub fn lookup(&self, symbol: Symbol) -> Option<Value> {
+        if let Some(value) = self.values.get(&symbol) {
+            return Some(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().lookup(symbol),
+            None => None,
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        let symbol = Self::symbol(name);
+
+        if let Some(slot) = self.values.get_mut(&symbol) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(Self::undefined(name)),
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        depth: Depth,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match depth {
+            Depth::Local(0) => match self.values.get_mut(&Self::symbol(name)) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(Self::undefined(name)),
+            },
+            Depth::Global(0) => self.assign(name, value),
+            _ => self
+                .enclosing(name)?
+                .borrow_mut()
+                .assign_at(depth.outer(), name, value),
+        }
+    }
+
+    //the next scope out on the way to where a name was resolved to. the parser's scopes line
+    //up with the environments, so running out of them is a bug in one or the other
+    fn enclosing(&self, name: &Token) -> Result<&Rc<RefCell<Environment>>, RuntimeError> {
+        debug_assert!(
+            self.enclosing.is_some(),
+            "'{}' resolved too far out",
+            name.lexeme
+        );
+
+        self.enclosing.as_ref().ok_or_else(|| {
+            RuntimeError::new(
+                ErrorKind::Runtime,
+                name,
+                &format!("'{}' was resolved past the outermost scope.", name.lexeme),
+            )
+        })
+    }
+
+    fn symbol(name: &Token) -> Symbol {
+        name.symbol.unwrap_or_else(|| Symbol::intern(&name.lexeme))
+    }
+
+    fn undefined(name: &Token) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::Name,
+            name,
+            &format!("Undefined variable '{}'.", name.lexeme),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokentype::*;
+
+    fn name(lexeme: &str) -> Token {
+        Token::new(Box::new(TokenType::Identifier), lexeme, "", 1)
+    }
+
+    //builtins <- globals <- block
+    fn scopes() -> Rc<RefCell<Environment>> {
+        let builtins = Rc::new(RefCell::new(Environment::new()));
+        builtins
+            .borrow_mut()
+            .define(Symbol::intern("builtin"), Value::Number(1.0));
+
+        let globals = Rc::new(RefCell::new(Environment::with_enclosing(builtins)));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("global"), Value::Number(2.0));
+
+        let block = Environment::with_enclosing(globals);
+        Rc::new(RefCell::new(block))
+    }
+
+    #[test]
+    fn locals_are_only_looked_for_where_they_were_declared() {
+        let block = scopes();
+        let block = block.borrow();
+
+        assert_eq!(
+            block.get_at(Depth::Local(1), &name("global")),
+            Ok(Value::Number(2.0))
+        );
+        assert!(block.get_at(Depth::Local(0), &name("global")).is_err());
+        assert!(block.get_at(Depth::Local(1), &name("builtin")).is_err());
+    }
+
+    #[test]
+    fn globals_fall_through_to_the_builtins() {
+        let block = scopes();
+
+        assert_eq!(
+            block.borrow().get_at(Depth::Global(1), &name("builtin")),
+            Ok(Value::Number(1.0))
+        );
+
+        block
+            .borrow_mut()
+            .assign_at(Depth::Global(1), &name("builtin"), Value::Nil)
+            .unwrap();
+        assert_eq!(
+            block.borrow().get_at(Depth::Global(1), &name("builtin")),
+            Ok(Value::Nil)
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use std::convert::From;
+use std::sync::Arc;
 
+use crate::scanner::parse_number;
+use crate::stmt::*;
+use crate::symbol::*;
 use crate::token::*;
 use crate::tokentype::*;
 
@@ -15,67 +19,161 @@ use crate::tokentype::*;
 //w.r.t. recursive descent?
 //I think the only alternative to that is having all the fields on Expr as Options which might be worse
 
+//how many scopes out from where a name is used it was declared
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Depth {
+    Local(usize),
+    //declared in no enclosing scope. the top level is this many scopes out, and the name is
+    //looked up there when it's used, since a global can be defined after the code using it or
+    //be a builtin
+    Global(usize),
+}
+
+impl Depth {
+    //the same declaration, from one scope further out
+    pub fn outer(self) -> Self {
+        match self {
+            Depth::Local(depth) => Depth::Local(depth - 1),
+            Depth::Global(depth) => Depth::Global(depth - 1),
+        }
+    }
+}
+
+//nodes that can fail at runtime keep their operator token around for error reporting
+#[derive(Debug)]
 pub enum Expr {
-    Equality(),
-    Comparison(),
-    Term(),
-    Factor(),
-    Binary(Box<Expr>, Operator, Box<Expr>),
-    Unary(Operator, Box<Expr>),
+    Binary(Box<Expr>, Operator, Box<Expr>, Token),
+    Unary(Operator, Box<Expr>, Token),
     Literal(Box<Literal>),
     Grouping(Box<Expr>),
+    //condition ? then : else
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    //a name, and where it's declared. the parser works that out as it goes
+    Variable(Token, Depth),
+    Assign(Token, Depth, Box<Expr>),
+    //object.name
+    Get(Box<Expr>, Token),
+    //object.name = value
+    Set(Box<Expr>, Token, Box<Expr>),
+    //target op= value, where target is a Variable or a Get. kept as its own node rather than
+    //desugared so the target's object is only evaluated once
+    CompoundAssign(Box<Expr>, Operator, Box<Expr>, Token),
+    //++target, target--, ...
+    Increment(Box<Expr>, Operator, Fixity, Token),
+    //callee(arguments), with the closing paren for error reporting
+    Call(Box<Expr>, Token, Vec<Expr>),
+    This(Token),
+    //super.method
+    Super(Token, Token),
+    //`fun (a) { ... }` or `(a) => ...`
+    Lambda(Arc<Function>),
+    //[a, b, c]
+    List(Vec<Expr>),
+    //{key: value, ...}, with the `{` for error reporting
+    Map(Vec<(Expr, Expr)>, Token),
+    //object[index]. the token's span covers the whole `[index]`, for error reporting
+    Index(Box<Expr>, Box<Expr>, Token),
+    //object[index] = value
+    SetIndex(Box<Expr>, Box<Expr>, Box<Expr>, Token),
+    //object[start:end], either bound can be left off
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>, Token),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fixity {
+    //gives back the updated value
+    Prefix,
+    //gives back the value from before the update
+    Postfix,
+}
+
+#[derive(Debug)]
 pub enum Literal {
-    Number(usize),
-    String(String),
+    Number(f64),
+    String(Symbol),
     True,
     False,
     Nil,
-    Expr(Expr),
+    Expr(Box<Expr>),
 }
 
-//TODO: this parses everything as a Number, obviously wrong, just getting it to build now
-//  bc I'm still not sure how to structure this with literals
-impl From<&str> for Literal {
-    fn from(s: &str) -> Self {
-        match s.to_lowercase() {
-            // "number" => Literal::Number(1),
-            // "string" => Literal::String("abc"),
-            _ => Literal::Number(1),
+//the scanner has already validated number lexemes, so parse_number only fails on a scanner bug
+impl From<&Token> for Literal {
+    fn from(token: &Token) -> Self {
+        match *token.token_type {
+            TokenType::Number => Literal::Number(parse_number(&token.literal).unwrap_or(f64::NAN)),
+            TokenType::String => Literal::String(
+                token
+                    .symbol
+                    .unwrap_or_else(|| Symbol::intern(&token.literal)),
+            ),
+            TokenType::True => Literal::True,
+            TokenType::False => Literal::False,
+            _ => Literal::Nil,
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Operator {
     Plus,
     Minus,
     Times,
     Divide,
+    IntDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
     Bang,
     Equal,
+    NotEqual,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
-    Invalid, //TODO: this is a placeholder for invalid conversion in the from() fn. will remove later
+    //`and` and `or` short circuit, and give back whichever operand decided the result
+    And,
+    Or,
+    //`a, b` evaluates both and gives back b
+    Comma,
 }
 
-impl From<&Token<'_>> for Operator {
-    fn from(token: &Token) -> Self {
-        match *token.token_type {
-            TokenType::Plus => Operator::Plus,
-            TokenType::Minus => Operator::Minus,
-            TokenType::Star => Operator::Times,
-            TokenType::Slash => Operator::Divide,
+//fails with the message for a token that isn't an operator
+impl TryFrom<&Token> for Operator {
+    type Error = String;
+
+    fn try_from(token: &Token) -> Result<Self, Self::Error> {
+        Ok(match *token.token_type {
+            TokenType::Plus | TokenType::PlusEqual | TokenType::PlusPlus => Operator::Plus,
+            TokenType::Minus | TokenType::MinusEqual | TokenType::MinusMinus => Operator::Minus,
+            TokenType::Star | TokenType::StarEqual => Operator::Times,
+            TokenType::Slash | TokenType::SlashEqual => Operator::Divide,
+            TokenType::TildeSlash => Operator::IntDivide,
+            TokenType::Percent | TokenType::PercentEqual => Operator::Modulo,
+            TokenType::StarStar => Operator::Power,
+            TokenType::Ampersand => Operator::BitAnd,
+            TokenType::Pipe => Operator::BitOr,
+            TokenType::Caret => Operator::BitXor,
+            TokenType::Tilde => Operator::BitNot,
+            TokenType::LessLess => Operator::ShiftLeft,
+            TokenType::GreaterGreater => Operator::ShiftRight,
             TokenType::Bang => Operator::Bang,
-            TokenType::Equal => Operator::Equal,
+            TokenType::EqualEqual => Operator::Equal,
+            TokenType::BangEqual => Operator::NotEqual,
             TokenType::Greater => Operator::Greater,
             TokenType::GreaterEqual => Operator::GreaterEqual,
             TokenType::Less => Operator::Less,
             TokenType::LessEqual => Operator::LessEqual,
-            _ => Operator::Invalid,
-        }
+            TokenType::And => Operator::And,
+            TokenType::Or => Operator::Or,
+            TokenType::Comma => Operator::Comma,
+            _ => return Err(format!("'{}' isn't an operator.", token.lexeme)),
+        })
     }
 }
 
@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::class::*;
+use crate::environment::*;
+use crate::interpreter::*;
+use crate::stmt::*;
+use crate::symbol::*;
+use crate::token::*;
+use crate::value::*;
+
+//a function value: the declaration plus the scope it was created in
+pub struct LoxFunction {
+    declaration: Arc<Function>,
+    closure: Rc<RefCell<Environment>>,
+    //an `init` method, which always gives back `this`
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(declaration: Arc<Function>, closure: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer: false,
+        }
+    }
+
+    pub fn method(declaration: Arc<Function>, closure: Rc<RefCell<Environment>>) -> Self {
+        let is_initializer = declaration
+            .name
+            .as_ref()
+            .is_some_and(|name| name.lexeme.as_ref() == "init");
+
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    //a copy of a method with `this` defined in a scope just outside its body
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> Self {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(Symbol::intern("this"), Value::Instance(instance));
+
+        Self {
+            declaration: Arc::clone(&self.declaration),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.declaration.name {
+            Some(name) => &name.lexeme,
+            None => "anonymous function",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    //the arity has already been checked
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            let symbol = param.symbol.expect("identifier tokens are always interned");
+            environment.define(symbol, argument);
+        }
+
+        let result =
+            interpreter.execute_block(&self.declaration.body, Rc::new(RefCell::new(environment)));
+
+        match result {
+            //an initializer gives back `this` even from a bare `return;`
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self
+                .closure
+                .borrow()
+                .lookup(Symbol::intern("this"))
+                .unwrap_or(Value::Nil)),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(mut err)) => {
+                err.trace.push(Frame {
+                    function: Rc::from(self.name()),
+                    line: paren.line,
+                });
+
+                Err(err)
+            }
+            //the parser doesn't let these cross a function boundary
+            Err(Unwind::Break(_) | Unwind::Continue(_)) => {
+                unreachable!("break or continue outside of a loop")
+            }
+        }
+    }
+}
+
+//the closure can hold this function, so printing it would recurse forever
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxFunction({self})")
+    }
+}
+
+impl fmt::Display for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.declaration.name {
+            Some(name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
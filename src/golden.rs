@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+//long enough for anything in the suite. a script still running after this is stuck in a loop
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+//what a test script expects to happen when it runs, read from comments in it, as in the
+//crafting interpreters test suite:
+//
+//print 1 + 2; // expect: 3
+//print nil.x; // expect runtime error: Only instances have properties.
+//var = 1; // Error at '=': Expect variable name.
+//
+//and `// [line N] Error ...` for a compile error reported on some other line, like the end
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    //the lines printed to stdout, in order
+    pub output: Vec<String>,
+    //compile errors, as printed to stderr
+    pub errors: Vec<String>,
+    //the message of a runtime error, and the line it's raised on
+    pub runtime_error: Option<(String, u32)>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+
+            //looked for by marker rather than at the first `//`, which could be the start of an
+            //ordinary comment before the expectation
+            if let Some(output) = after(line, "// expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = after(line, "// expect runtime error: ") {
+                expectations.runtime_error = Some((message.to_string(), line_number));
+            } else if let Some(error) = after(line, "// Error") {
+                expectations
+                    .errors
+                    .push(format!("[line {line_number}] Error{error}"));
+            } else if let Some(error) =
+                after(line, "// [line ").or_else(|| after(line, "// [java line "))
+            {
+                //a `[c line N]` error is one only clox reports. this is a tree-walker like jlox
+                if error.contains("] Error") {
+                    expectations.errors.push(format!("[line {error}"));
+                }
+            }
+        }
+
+        expectations
+    }
+
+    //65 for compile errors and 70 for runtime errors, like `lox` itself
+    pub fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+//the rest of the line after the first marker in it
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker).map(|start| &line[start + marker.len()..])
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub path: PathBuf,
+    //why the test failed. empty if it passed
+    pub failures: Vec<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+//every .lox file under dir, in a stable order
+pub fn find_tests(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut tests = vec![];
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            tests.extend(find_tests(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            tests.push(path);
+        }
+    }
+
+    Ok(tests)
+}
+
+//runs every test under dir with the `lox` binary at interpreter
+pub fn run_suite(interpreter: &Path, dir: &Path) -> io::Result<Vec<TestResult>> {
+    find_tests(dir)?
+        .into_iter()
+        .map(|path| run_test(interpreter, &path))
+        .collect()
+}
+
+//the results of running every test in a directory, checked against the tests the directory's
+//known_failures.txt says are expected to fail. tests under the paths in its skipped.txt
+//aren't run at all
+#[derive(Debug)]
+pub struct Suite {
+    pub dir: PathBuf,
+    pub results: Vec<TestResult>,
+    pub known_failures: HashSet<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Suite {
+    pub fn run(interpreter: &Path, dir: &Path) -> io::Result<Self> {
+        let skip = read_list(dir, "skipped.txt")?;
+        let (skipped, tests): (Vec<_>, Vec<_>) = find_tests(dir)?
+            .into_iter()
+            .partition(|path| skip.iter().any(|skip| path.starts_with(skip)));
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            results: tests
+                .iter()
+                .map(|path| run_test(interpreter, path))
+                .collect::<io::Result<_>>()?,
+            known_failures: read_known_failures(dir)?,
+            skipped,
+        })
+    }
+
+    fn is_known_failure(&self, result: &TestResult) -> bool {
+        self.known_failures.contains(&result.path)
+    }
+
+    //tests that failed without being on the list
+    pub fn regressions(&self) -> Vec<&TestResult> {
+        self.results
+            .iter()
+            .filter(|result| !result.passed() && !self.is_known_failure(result))
+            .collect()
+    }
+
+    //tests on the list that pass now, so should come off it
+    pub fn fixed(&self) -> Vec<&TestResult> {
+        self.results
+            .iter()
+            .filter(|result| result.passed() && self.is_known_failure(result))
+            .collect()
+    }
+
+    //true if every test did what known_failures.txt says it would
+    pub fn as_expected(&self) -> bool {
+        self.regressions().is_empty() && self.fixed().is_empty()
+    }
+
+    //a test's category is the directory it's in under the suite's, or its own name if it's
+    //at the top
+    pub fn category(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+
+        match relative.components().count() {
+            0 | 1 => relative.file_stem(),
+            _ => relative.components().next().map(|first| first.as_os_str()),
+        }
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+    }
+
+    //the failures nobody expected, then how many tests passed in each category
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        for result in self.regressions() {
+            let _ = writeln!(report, "FAIL {}", result.path.display());
+
+            for failure in &result.failures {
+                let _ = writeln!(report, "     {failure}");
+            }
+        }
+
+        for result in self.fixed() {
+            let _ = writeln!(
+                report,
+                "PASS {} is listed in known_failures.txt but passes now",
+                result.path.display()
+            );
+        }
+
+        //passed and total, by category
+        let mut categories: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+
+        for result in &self.results {
+            let (passed, total) = categories.entry(self.category(&result.path)).or_default();
+
+            *passed += usize::from(result.passed());
+            *total += 1;
+        }
+
+        let _ = writeln!(report);
+
+        for (category, (passed, total)) in &categories {
+            let status = if passed == total { "ok" } else { "FAILING" };
+            let _ = writeln!(report, "{category:<24} {passed:>3}/{total:<3} {status}");
+        }
+
+        let failed = self
+            .results
+            .iter()
+            .filter(|result| !result.passed())
+            .count();
+        let _ = writeln!(
+            report,
+            "\n{} tests passed. {failed} tests failed, {} of them not known to fail. {} skipped.",
+            self.results.len() - failed,
+            self.regressions().len(),
+            self.skipped.len()
+        );
+
+        report
+    }
+}
+
+//the paths in known_failures.txt, relative to dir
+pub fn read_known_failures(dir: &Path) -> io::Result<HashSet<PathBuf>> {
+    Ok(read_list(dir, "known_failures.txt")?.into_iter().collect())
+}
+
+//the paths listed in a file in dir, relative to dir. blank lines and `#` comments are skipped,
+//and so is anything after a path, which is for saying why it's there. no file is no paths
+fn read_list(dir: &Path, name: &str) -> io::Result<Vec<PathBuf>> {
+    let contents = match fs::read_to_string(dir.join(name)) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|path| !path.starts_with('#'))
+        .map(|path| dir.join(path))
+        .collect())
+}
+
+//runs the script in its own process, so its exit code can be checked and a crash only takes
+//down that one test
+pub fn run_test(interpreter: &Path, path: &Path) -> io::Result<TestResult> {
+    let expectations = Expectations::parse(&fs::read_to_string(path)?);
+
+    let mut child = Command::new(interpreter)
+        .arg(path)
+        //modules should only come from next to the test
+        .env_remove("LOX_PATH")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    //read while the script runs, so it can't block on a full pipe
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let status = wait(&mut child)?;
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+
+    let mut failures = vec![];
+
+    let status = match status {
+        Some(status) => status,
+        None => {
+            failures.push(format!("Timed out after {} seconds.", TIMEOUT.as_secs()));
+
+            return Ok(TestResult {
+                path: path.to_path_buf(),
+                failures,
+            });
+        }
+    };
+
+    check_output(&expectations, &stdout, &mut failures);
+
+    match &expectations.runtime_error {
+        Some(runtime_error) => check_runtime_error(runtime_error, &stderr, &mut failures),
+        None => check_errors(&expectations, &stderr, &mut failures),
+    }
+
+    match status.code() {
+        Some(code) if code == expectations.exit_code() => {}
+        Some(code) => failures.push(format!(
+            "Expected exit code {} but got {code}.",
+            expectations.exit_code()
+        )),
+        None => failures.push(String::from("Killed by a signal.")),
+    }
+
+    Ok(TestResult {
+        path: path.to_path_buf(),
+        failures,
+    })
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = vec![];
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+//None if the script had to be killed for running too long
+fn wait(child: &mut Child) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() > TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn check_output(expectations: &Expectations, stdout: &str, failures: &mut Vec<String>) {
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    for (i, expected) in expectations.output.iter().enumerate() {
+        match lines.get(i) {
+            Some(line) if line == expected => {}
+            Some(line) => failures.push(format!("Expected output '{expected}' but got '{line}'.")),
+            None => failures.push(format!("Missing expected output '{expected}'.")),
+        }
+    }
+
+    for line in lines.iter().skip(expectations.output.len()) {
+        failures.push(format!("Got output '{line}' when none was expected."));
+    }
+}
+
+//the message, then the line it was raised on. anything after that is a stack trace
+fn check_runtime_error((message, line): &(String, u32), stderr: &str, failures: &mut Vec<String>) {
+    let mut lines = stderr.lines().filter(|line| !is_warning(line));
+
+    match lines.next() {
+        Some(actual) if actual == message => {}
+        Some(actual) => failures.push(format!(
+            "Expected runtime error '{message}' but got '{actual}'."
+        )),
+        None => {
+            failures.push(format!("Expected runtime error '{message}' but got none."));
+            return;
+        }
+    }
+
+    let expected_line = format!("[line {line}]");
+
+    match lines.next() {
+        Some(actual) if actual == expected_line => {}
+        actual => failures.push(format!(
+            "Expected '{expected_line}' after the runtime error but got '{}'.",
+            actual.unwrap_or_default()
+        )),
+    }
+}
+
+//warnings aren't errors, so tests don't have to expect them
+fn check_errors(expectations: &Expectations, stderr: &str, failures: &mut Vec<String>) {
+    let lines: Vec<&str> = stderr.lines().filter(|line| !is_warning(line)).collect();
+
+    for expected in &expectations.errors {
+        if !lines.contains(&expected.as_str()) {
+            failures.push(format!("Missing expected error '{expected}'."));
+        }
+    }
+
+    for line in lines {
+        if !expectations.errors.iter().any(|expected| expected == line) {
+            failures.push(format!("Unexpected output on stderr '{line}'."));
+        }
+    }
+}
+
+fn is_warning(line: &str) -> bool {
+    line.starts_with("[line ") && line.contains("] Warning")
+}
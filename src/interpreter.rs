@@ -1,27 +1,1515 @@
-use crate::Expr::*;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
 
-struct Interpreter {}
+use crate::builtins::*;
+use crate::class::*;
+use crate::environment::*;
+use crate::expr::*;
+use crate::function::*;
+use crate::iterator::*;
+use crate::map::*;
+use crate::module::*;
+use crate::native::*;
+use crate::native_class::*;
+use crate::output::*;
+use crate::parser::*;
+use crate::scanner::*;
+use crate::stmt::*;
+use crate::symbol::*;
+use crate::token::*;
+use crate::value::*;
 
-//TODO: using Object as a return type placeholder here
-//this is the visitor pattern to get around the expression problem in OOP
-//I believe this is best alleviated w/ traits in Rust so gotta change later
+#[derive(Debug)]
+pub struct Interpreter {
+    pub globals: Rc<RefCell<Environment>>,
+    //the builtins and the prelude. every module's globals sit on top of this
+    builtins: Rc<RefCell<Environment>>,
+    //the innermost scope of whatever is executing right now
+    environment: Rc<RefCell<Environment>>,
+    //the `Error` class from the prelude, which caught runtime errors are instances of
+    error_class: Rc<LoxClass>,
+    //the file running right now, which imports are relative to. None for the REPL
+    current_file: Option<PathBuf>,
+    //modules already loaded, by canonical path, so each one only runs once
+    modules: HashMap<PathBuf, Rc<LoxModule>>,
+    //modules part way through loading, to catch import cycles
+    loading: Vec<PathBuf>,
+    //where to look for modules that aren't next to the importing file, in order
+    search_path: Vec<PathBuf>,
+    //classes implemented in rust, by the rust type they wrap
+    native_classes: HashMap<TypeId, Rc<LoxNativeClass>>,
+    //where `print` writes to
+    output: Output,
+    //calls into lox functions and classes that haven't returned yet
+    call_depth: usize,
+}
+
+//how deep lox calls can nest before raising "Stack overflow."
+const MAX_CALL_DEPTH: usize = 2048;
+//when less stack than the red zone is left at a call, a new segment of this size is used
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+//written in lox so scripts can subclass Error like any other class
+const PRELUDE: &str = "class Error { init(message) { this.message = message; } }";
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub line: u32,
+    pub message: String,
+    pub span: Span,
+    pub kind: ErrorKind,
+    //what a `throw` threw. None for errors raised by the interpreter itself
+    pub value: Option<Value>,
+    //the calls the error has unwound out of so far, innermost first
+    pub trace: Vec<Frame>,
+}
+
+//a function call on the way out of which an error was seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: Rc<str>,
+    //where the function was called from
+    pub line: u32,
+}
+
+//what sort of failure an error from the interpreter was, so a catch clause can tell them apart
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorKind {
+    Runtime,
+    Type,
+    //an undefined variable
+    Name,
+    Property,
+    Arity,
+    //an index or key that isn't there
+    Index,
+    StackOverflow,
+    Import,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Runtime => "RuntimeError",
+            ErrorKind::Type => "TypeError",
+            ErrorKind::Name => "NameError",
+            ErrorKind::Property => "PropertyError",
+            ErrorKind::Arity => "ArityError",
+            ErrorKind::Index => "IndexError",
+            ErrorKind::StackOverflow => "StackOverflowError",
+            ErrorKind::Import => "ImportError",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, token: &Token, message: &str) -> Self {
+        Self {
+            line: token.line,
+            message: message.to_string(),
+            span: token.span,
+            kind,
+            value: None,
+            trace: vec![],
+        }
+    }
+
+    //one line per frame, innermost first, ending with the top level script
+    pub fn stack_trace(&self) -> Vec<String> {
+        let mut lines = vec![];
+        let mut line = self.line;
+
+        for frame in &self.trace {
+            lines.push(format!("[line {line}] in {}()", frame.function));
+            line = frame.line;
+        }
+
+        lines.push(format!("[line {line}] in script"));
+        lines
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.line)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+//why a statement stopped executing early. a `return` travels up through the blocks of a
+//function body the same way an error does, until the call catches it. `break` and `continue`
+//travel up to their loop, carrying its label if they named one
+#[derive(Debug)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    Break(Option<Symbol>),
+    Continue(Option<Symbol>),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(err: RuntimeError) -> Self {
+        Unwind::Error(err)
+    }
+}
 
 impl Interpreter {
-    pub fn literal_expr(expr: Expr::Literal) -> Expr::Literal {
-        // expr.value
-        expr
+    pub fn new() -> Self {
+        let builtins = Rc::new(RefCell::new(Environment::new()));
+
+        let prelude = Parser::from_scanner(Scanner::new(PRELUDE))
+            .parse()
+            .unwrap_or_else(|_| unreachable!("the prelude parses"));
+
+        //a placeholder until the prelude has defined the real thing
+        let placeholder = LoxClass::new(Rc::from("Error"), None, HashMap::new());
+
+        let mut interpreter = Self {
+            globals: Rc::clone(&builtins),
+            environment: Rc::clone(&builtins),
+            builtins,
+            error_class: Rc::new(placeholder),
+            current_file: None,
+            modules: HashMap::new(),
+            loading: vec![],
+            search_path: vec![],
+            native_classes: HashMap::new(),
+            output: Output::stdout(),
+            call_depth: 0,
+        };
+
+        define_builtins(&mut interpreter);
+
+        interpreter
+            .interpret(&prelude)
+            .unwrap_or_else(|_| unreachable!("the prelude runs"));
+
+        if let Some(Value::Class(class)) = interpreter
+            .builtins
+            .borrow()
+            .lookup(Symbol::intern("Error"))
+        {
+            interpreter.error_class = class;
+        }
+
+        let globals = Environment::with_enclosing(Rc::clone(&interpreter.builtins));
+        interpreter.globals = Rc::new(RefCell::new(globals));
+        interpreter.environment = Rc::clone(&interpreter.globals);
+
+        interpreter
+    }
+
+    //sets the file imports in the main script are relative to. the script counts as loading
+    //while it runs, so a module importing it back is a cycle
+    pub fn set_script_path(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.loading = vec![path.clone()];
+        self.current_file = Some(path);
+    }
+
+    //makes a rust function callable from every script and module as a global `name`.
+    //defining a name again replaces the old definition
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+
+        self.builtins
+            .borrow_mut()
+            .define(Symbol::intern(name), Value::Native(Rc::new(native)));
+    }
+
+    //makes a rust type usable from every script and module as a global class
+    pub fn define_class<T: NativeClass>(&mut self) {
+        let class = self.native_class::<T>();
+
+        self.builtins
+            .borrow_mut()
+            .define(Symbol::intern(T::NAME), Value::NativeClass(class));
+    }
+
+    //wraps a rust value so scripts can use it. its class doesn't have to have been defined
+    pub fn native_instance<T: NativeClass>(&mut self, value: T) -> Value {
+        let class = self.native_class::<T>();
+
+        Value::NativeInstance(Rc::new(LoxNativeInstance::new(class, value)))
+    }
+
+    //each rust type gets one class, made the first time it's needed
+    fn native_class<T: NativeClass>(&mut self) -> Rc<LoxNativeClass> {
+        let class = self
+            .native_classes
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Rc::new(LoxNativeClass::new::<T>()));
+
+        Rc::clone(class)
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn flush_output(&mut self) {
+        let _ = self.output.flush();
+    }
+
+    pub fn add_search_path(&mut self, dir: &Path) {
+        self.search_path.push(dir.to_path_buf());
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        self.eval(statements).map(|_| ())
+    }
+
+    //like interpret, but gives back the value of the last statement if it's an expression
+    //statement, and nil otherwise
+    pub fn eval(&mut self, statements: &[Stmt]) -> Result<Value, RuntimeError> {
+        let (last, rest) = match statements.split_last() {
+            Some((Stmt::Expression(expr), rest)) => (Some(expr), rest),
+            _ => (None, statements),
+        };
+
+        for statement in rest {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(err)) => return Err(err),
+                //the parser rejects these outside of a function or loop
+                Err(Unwind::Return(_)) => unreachable!("return outside of a function"),
+                Err(Unwind::Break(_) | Unwind::Continue(_)) => {
+                    unreachable!("break or continue outside of a loop")
+                }
+            }
+        }
+
+        match last {
+            Some(expr) => self.evaluate(expr),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print(keyword, expr) => {
+                let value = self.evaluate(expr)?;
+
+                //e.g. stdout is a pipe that's been closed
+                writeln!(self.output, "{value}").map_err(|err| {
+                    RuntimeError::new(
+                        ErrorKind::Runtime,
+                        keyword,
+                        &format!("Can't write output: {err}."),
+                    )
+                })?;
+            }
+            Stmt::Var(name, initializer) => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+                self.environment.borrow_mut().define(symbol, value);
+            }
+            Stmt::Block(statements) => {
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, Rc::new(RefCell::new(environment)))?;
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While(label, condition, body, increment) => {
+                let label = label.as_ref().and_then(|label| label.symbol);
+
+                while self.evaluate(condition)?.is_truthy() {
+                    if !Self::loop_body(label, self.execute(body))? {
+                        break;
+                    }
+
+                    if let Some(increment) = increment {
+                        self.evaluate(increment)?;
+                    }
+                }
+            }
+            Stmt::ForIn(label, names, iterable, body, keyword) => {
+                let label = label.as_ref().and_then(|label| label.symbol);
+                let iterable = self.evaluate(iterable)?;
+                let mut iterator = LoxIterator::new(self, iterable, names.len() == 2, keyword)?;
+
+                while let Some(item) = iterator.next(self, keyword)? {
+                    //each pass gets a fresh scope, so closures made in the body keep their own
+                    //copy of the loop variables
+                    let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                    let symbol =
+                        |name: &Token| name.symbol.expect("identifier tokens are always interned");
+
+                    match &names[..] {
+                        [name] => environment.define(symbol(name), item),
+                        [first, second] => {
+                            let (a, b) = Self::unpack_pair(item, keyword)?;
+                            environment.define(symbol(first), a);
+                            environment.define(symbol(second), b);
+                        }
+                        _ => unreachable!("for-in has one or two loop variables"),
+                    }
+
+                    let result = self.execute_block(
+                        std::slice::from_ref(body.as_ref()),
+                        Rc::new(RefCell::new(environment)),
+                    );
+
+                    if !Self::loop_body(label, result)? {
+                        break;
+                    }
+                }
+            }
+            Stmt::Break(_, label) => {
+                return Err(Unwind::Break(label.as_ref().and_then(|label| label.symbol)));
+            }
+            Stmt::Continue(_, label) => {
+                return Err(Unwind::Continue(
+                    label.as_ref().and_then(|label| label.symbol),
+                ));
+            }
+            Stmt::Class(name, superclass, methods) => self.class(name, superclass, methods)?,
+            Stmt::Function(declaration) => {
+                let function =
+                    LoxFunction::new(Arc::clone(declaration), Rc::clone(&self.environment));
+                let name = declaration
+                    .name
+                    .as_ref()
+                    .expect("declared functions have names");
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                self.environment
+                    .borrow_mut()
+                    .define(symbol, Value::Function(Rc::new(function)));
+            }
+            Stmt::Throw(keyword, value) => {
+                let value = self.evaluate(value)?;
+
+                let message = match self.error_field(&value, "message") {
+                    Some(message) => message.to_string(),
+                    None => value.to_string(),
+                };
+
+                let mut error = RuntimeError::new(ErrorKind::Runtime, keyword, &message);
+                error.value = Some(value);
+
+                return Err(Unwind::Error(error));
+            }
+            //finally runs however the rest of the statement finished. if it finishes early
+            //itself, that wins over whatever was going on before
+            Stmt::Try(body, catch, finally) => {
+                let result = self.execute_block(body, self.scope());
+
+                let result = match (result, catch) {
+                    (Err(Unwind::Error(error)), Some((name, handler))) => {
+                        let symbol = name.symbol.expect("identifier tokens are always interned");
+                        let environment = self.scope();
+                        environment.borrow_mut().define(symbol, self.caught(error));
+
+                        self.execute_block(handler, environment)
+                    }
+                    (result, _) => result,
+                };
+
+                if let Some(finally) = finally {
+                    self.execute_block(finally, self.scope())?;
+                }
+
+                result?;
+            }
+            Stmt::Import(keyword, path, name) => {
+                let module = self.import(keyword, path)?;
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                self.environment
+                    .borrow_mut()
+                    .define(symbol, Value::Module(module));
+            }
+            //the exported names were already collected when the module was parsed
+            Stmt::Export(declaration) => self.execute(declaration)?,
+            Stmt::Match(_, value, arms) => {
+                let value = self.evaluate(value)?;
+
+                for (pattern, body) in arms {
+                    let mut bindings = vec![];
+
+                    if self.matches(pattern, &value, &mut bindings)? {
+                        let environment = self.scope();
+
+                        for (symbol, value) in bindings {
+                            environment.borrow_mut().define(symbol, value);
+                        }
+
+                        self.execute_block(std::slice::from_ref(body), environment)?;
+                        break;
+                    }
+                }
+            }
+            Stmt::Return(_, value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+
+                return Err(Unwind::Return(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    //runs `statements` in `environment`, putting the current one back afterwards even on error
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), Unwind> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+
+        self.environment = previous;
+        result
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Literal(literal) => Ok(Self::literal_value(literal)),
+            Expr::Grouping(inner) => self.evaluate(inner),
+            Expr::Unary(operator, right, token) => {
+                let right = self.evaluate(right)?;
+
+                match (operator, right) {
+                    (Operator::Bang, right) => Ok(Value::Bool(!right.is_truthy())),
+                    (Operator::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+                    (Operator::Minus, _) => Err(RuntimeError::new(
+                        ErrorKind::Type,
+                        token,
+                        "Operand must be a number.",
+                    )),
+                    (Operator::BitNot, right) => {
+                        let n = Self::integer_operand(&right, token)?;
+
+                        Ok(Value::Number(!n as f64))
+                    }
+                    _ => Err(RuntimeError::new(
+                        ErrorKind::Runtime,
+                        token,
+                        "Invalid unary operator.",
+                    )),
+                }
+            }
+            Expr::Binary(left, Operator::Comma, right, _) => {
+                self.evaluate(left)?;
+                self.evaluate(right)
+            }
+            Expr::Binary(left, Operator::Or, right, _) => {
+                let left = self.evaluate(left)?;
+
+                if left.is_truthy() {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Binary(left, Operator::And, right, _) => {
+                let left = self.evaluate(left)?;
+
+                if !left.is_truthy() {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Binary(left, operator, right, token) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+
+                Self::binary(*operator, left, right, token)
+            }
+            Expr::Variable(name, depth) => self.environment.borrow().get_at(*depth, name),
+            Expr::Assign(name, depth, value) => {
+                let value = self.evaluate(value)?;
+                self.environment
+                    .borrow_mut()
+                    .assign_at(*depth, name, value.clone())?;
+
+                Ok(value)
+            }
+            Expr::Get(object, name) => {
+                let object = self.evaluate(object)?;
+
+                Self::get_property(&object, name)
+            }
+            Expr::Set(object, name, value) => {
+                let object = self.evaluate(object)?;
+                let value = self.evaluate(value)?;
+
+                Self::set_property(&object, name, value.clone())?;
+                Ok(value)
+            }
+            Expr::CompoundAssign(target, operator, value, token) => {
+                let (_, updated) = self.update(target, |interpreter, current| {
+                    let value = interpreter.evaluate(value)?;
+                    Self::binary(*operator, current, value, token)
+                })?;
+
+                Ok(updated)
+            }
+            Expr::Increment(target, operator, fixity, token) => {
+                let (old, updated) = self.update(target, |_, current| match current {
+                    Value::Number(n) => {
+                        Self::binary(*operator, Value::Number(n), Value::Number(1.0), token)
+                    }
+                    _ => Err(RuntimeError::new(
+                        ErrorKind::Type,
+                        token,
+                        "Operand must be a number.",
+                    )),
+                })?;
+
+                match fixity {
+                    Fixity::Prefix => Ok(updated),
+                    Fixity::Postfix => Ok(old),
+                }
+            }
+            Expr::Call(callee, paren, arguments) => {
+                let callee = self.evaluate(callee)?;
+
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.call_value(callee, arguments, paren)
+            }
+            Expr::This(keyword) => self.environment.borrow().get(keyword),
+            Expr::Super(keyword, method) => {
+                let superclass = self.environment.borrow().get(keyword)?;
+                let instance = self.environment.borrow().lookup(Symbol::intern("this"));
+
+                match (superclass, instance) {
+                    (Value::Class(superclass), Some(Value::Instance(instance))) => {
+                        let symbol = method
+                            .symbol
+                            .expect("identifier tokens are always interned");
+
+                        match superclass.find_method(symbol) {
+                            Some(method) => Ok(Value::Function(Rc::new(method.bind(instance)))),
+                            None => Err(Self::undefined_property(method)),
+                        }
+                    }
+                    //the parser only allows `super` inside a subclass's methods
+                    _ => unreachable!("super outside of a subclass method"),
+                }
+            }
+            Expr::Lambda(declaration) => Ok(Value::Function(Rc::new(LoxFunction::new(
+                Arc::clone(declaration),
+                Rc::clone(&self.environment),
+            )))),
+            Expr::List(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Value::List(Rc::new(RefCell::new(elements))))
+            }
+            //entries are evaluated in order, a repeated key keeps its first position
+            Expr::Map(entries, brace) => {
+                let mut map = LoxMap::new();
+
+                for (key, value) in entries {
+                    let key = self.evaluate(key)?;
+                    let key = Self::map_key(&key, brace)?;
+                    let value = self.evaluate(value)?;
+
+                    map.insert(key, value);
+                }
+
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            Expr::Index(object, index, bracket) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+
+                Self::get_index(&object, &index, bracket)
+            }
+            Expr::SetIndex(object, index, value, bracket) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+
+                Self::set_index(&object, &index, value.clone(), bracket)?;
+                Ok(value)
+            }
+            Expr::Slice(object, start, end, bracket) => {
+                let object = self.evaluate(object)?;
+                let start = match start {
+                    Some(start) => Some(self.evaluate(start)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(self.evaluate(end)?),
+                    None => None,
+                };
+
+                Self::slice(&object, start, end, bracket)
+            }
+            //only the branch that was picked gets evaluated
+            Expr::Ternary(condition, then_branch, else_branch) => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.evaluate(then_branch)
+                } else {
+                    self.evaluate(else_branch)
+                }
+            }
+        }
+    }
+
+    fn binary(
+        operator: Operator,
+        left: Value,
+        right: Value,
+        token: &Token,
+    ) -> Result<Value, RuntimeError> {
+        use Value::*;
+
+        match (operator, left, right) {
+            (Operator::Equal, left, right) => Ok(Bool(left == right)),
+            (Operator::NotEqual, left, right) => Ok(Bool(left != right)),
+            (Operator::Plus, Number(a), Number(b)) => Ok(Number(a + b)),
+            (Operator::Plus, String(a), String(b)) => Ok(String(Rc::from(format!("{a}{b}")))),
+            (Operator::Plus, _, _) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                token,
+                "Operands must be two numbers or two strings.",
+            )),
+            (Operator::Minus, Number(a), Number(b)) => Ok(Number(a - b)),
+            (Operator::Times, Number(a), Number(b)) => Ok(Number(a * b)),
+            (Operator::Divide, Number(a), Number(b)) => Ok(Number(a / b)),
+            //floored, so that a == (a ~/ b) * b + a % b holds, and the remainder takes the
+            //sign of the divisor. dividing by zero follows f64 and gives inf or NaN
+            (Operator::IntDivide, Number(a), Number(b)) => Ok(Number((a / b).floor())),
+            (Operator::Modulo, Number(a), Number(b)) => Ok(Number(a - b * (a / b).floor())),
+            (Operator::Power, Number(a), Number(b)) => Ok(Number(a.powf(b))),
+            (
+                Operator::BitAnd
+                | Operator::BitOr
+                | Operator::BitXor
+                | Operator::ShiftLeft
+                | Operator::ShiftRight,
+                left,
+                right,
+            ) => {
+                let a = Self::integer_operand(&left, token)?;
+                let b = Self::integer_operand(&right, token)?;
+
+                Self::bitwise(operator, a, b, token).map(|n| Number(n as f64))
+            }
+            (Operator::Greater, Number(a), Number(b)) => Ok(Bool(a > b)),
+            (Operator::GreaterEqual, Number(a), Number(b)) => Ok(Bool(a >= b)),
+            (Operator::Less, Number(a), Number(b)) => Ok(Bool(a < b)),
+            (Operator::LessEqual, Number(a), Number(b)) => Ok(Bool(a <= b)),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                token,
+                "Operands must be numbers.",
+            )),
+        }
+    }
+
+    //read-modify-write of an assignment target for the compound operators. the object of a
+    //property target is evaluated once, then read from and written back to.
+    //returns the old and the new value
+    fn update<F>(&mut self, target: &Expr, modify: F) -> Result<(Value, Value), RuntimeError>
+    where
+        F: FnOnce(&mut Self, Value) -> Result<Value, RuntimeError>,
+    {
+        match target {
+            Expr::Variable(name, depth) => {
+                let old = self.environment.borrow().get_at(*depth, name)?;
+                let updated = modify(self, old.clone())?;
+                self.environment
+                    .borrow_mut()
+                    .assign_at(*depth, name, updated.clone())?;
+
+                Ok((old, updated))
+            }
+            Expr::Get(object, name) => {
+                let object = self.evaluate(object)?;
+                let old = Self::get_property(&object, name)?;
+                let updated = modify(self, old.clone())?;
+                Self::set_property(&object, name, updated.clone())?;
+
+                Ok((old, updated))
+            }
+            Expr::Index(object, index, bracket) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let old = Self::get_index(&object, &index, bracket)?;
+                let updated = modify(self, old.clone())?;
+                Self::set_index(&object, &index, updated.clone(), bracket)?;
+
+                Ok((old, updated))
+            }
+            //the parser only hands us variables, property gets and indexes
+            _ => unreachable!("invalid assignment target"),
+        }
+    }
+
+    //checks `value` against `pattern`, collecting what it binds along the way. the bindings
+    //are only used if the whole pattern matches
+    fn matches(
+        &mut self,
+        pattern: &Pattern,
+        value: &Value,
+        bindings: &mut Vec<(Symbol, Value)>,
+    ) -> Result<bool, RuntimeError> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Binding(name) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+                bindings.push((symbol, value.clone()));
+
+                Ok(true)
+            }
+            Pattern::Literal(literal) => Ok(Self::literal_value(literal) == *value),
+            Pattern::Alternation(alternatives) => {
+                for alternative in alternatives {
+                    let bound = bindings.len();
+
+                    if self.matches(alternative, value, bindings)? {
+                        return Ok(true);
+                    }
+
+                    bindings.truncate(bound);
+                }
+
+                Ok(false)
+            }
+            Pattern::List(patterns) => {
+                let elements = match value {
+                    Value::List(elements) => elements.borrow().clone(),
+                    _ => return Ok(false),
+                };
+
+                if elements.len() != patterns.len() {
+                    return Ok(false);
+                }
+
+                for (pattern, element) in patterns.iter().zip(&elements) {
+                    if !self.matches(pattern, element, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            Pattern::Instance(class_name, depth, fields) => {
+                let class = match self.environment.borrow().get_at(*depth, class_name)? {
+                    Value::Class(class) => class,
+                    _ => {
+                        return Err(RuntimeError::new(
+                            ErrorKind::Type,
+                            class_name,
+                            &format!("'{}' is not a class.", class_name.lexeme),
+                        ))
+                    }
+                };
+
+                let instance = match value {
+                    Value::Instance(instance) if instance.class.is_subclass_of(&class) => instance,
+                    _ => return Ok(false),
+                };
+
+                for (field, pattern) in fields {
+                    let symbol = field.symbol.expect("identifier tokens are always interned");
+
+                    match instance.field(symbol) {
+                        Some(field) if self.matches(pattern, &field, bindings)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+
+                Ok(true)
+            }
+        }
+    }
+
+    //loads a module, or hands back the cached one if it's been loaded before
+    fn import(&mut self, keyword: &Token, path: &Token) -> Result<Rc<LoxModule>, RuntimeError> {
+        let path = self.resolve_module(&path.literal, keyword)?;
+
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(Rc::clone(module));
+        }
+
+        if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+            let cycle = self.loading[start..]
+                .iter()
+                .chain([&path])
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(RuntimeError::new(
+                ErrorKind::Import,
+                keyword,
+                &format!("Import cycle: {cycle}."),
+            ));
+        }
+
+        let source = fs::read_to_string(&path).map_err(|err| {
+            RuntimeError::new(
+                ErrorKind::Import,
+                keyword,
+                &format!("Can't read module '{}': {err}.", path.display()),
+            )
+        })?;
+
+        let statements = Self::parse_module(&source, &path, keyword)?;
+
+        let exports = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Stmt::Export(declaration) => declaration.declared_name(),
+                _ => None,
+            })
+            .map(|name| name.symbol.expect("identifier tokens are always interned"))
+            .collect();
+
+        //each module gets globals of its own
+        let globals = Rc::new(RefCell::new(Environment::with_enclosing(Rc::clone(
+            &self.builtins,
+        ))));
+
+        self.loading.push(path.clone());
+        let importer = self.current_file.replace(path.clone());
+        let result = self.execute_block(&statements, Rc::clone(&globals));
+        self.current_file = importer;
+        self.loading.pop();
+
+        match result {
+            Ok(()) => {}
+            Err(Unwind::Error(err)) => return Err(err),
+            //the parser rejects these outside of a function or loop
+            Err(_) => unreachable!("return, break or continue at the top level of a module"),
+        }
+
+        let name = Rc::from(path.display().to_string());
+        let module = Rc::new(LoxModule::new(name, globals, exports));
+        self.modules.insert(path, Rc::clone(&module));
+
+        Ok(module)
+    }
+
+    //a path starting with ./ or ../ (or an absolute one) is only looked for relative to the
+    //importing file, or the working directory in the REPL. anything else is looked for there
+    //first and then in each directory of the search path. ".lox" can be left off
+    fn resolve_module(&self, path: &str, keyword: &Token) -> Result<PathBuf, RuntimeError> {
+        let base = match &self.current_file {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => env::current_dir().unwrap_or_default(),
+        };
+
+        let explicit =
+            path.starts_with("./") || path.starts_with("../") || Path::new(path).is_absolute();
+
+        let mut dirs = vec![base];
+        if !explicit {
+            dirs.extend(self.search_path.iter().cloned());
+        }
+
+        let mut names = vec![PathBuf::from(path)];
+        if Path::new(path).extension().is_none() {
+            names.push(PathBuf::from(format!("{path}.lox")));
+        }
+
+        for dir in &dirs {
+            for name in &names {
+                let candidate = dir.join(name);
+
+                if candidate.is_file() {
+                    return Ok(fs::canonicalize(&candidate).unwrap_or(candidate));
+                }
+            }
+        }
+
+        let tried = dirs
+            .iter()
+            .map(|dir| format!("\n    {}", dir.display()))
+            .collect::<String>();
+
+        Err(RuntimeError::new(
+            ErrorKind::Import,
+            keyword,
+            &format!("Can't find module '{path}'. Looked in:{tried}"),
+        ))
+    }
+
+    //errors in a module are reported as one runtime error at the import that loaded it
+    fn parse_module(source: &str, path: &Path, keyword: &Token) -> Result<Vec<Stmt>, RuntimeError> {
+        let mut parser = Parser::from_scanner(Scanner::new(source));
+        let statements = parser.parse();
+
+        let mut errors = parser
+            .scan_errors()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+
+        let statements = match statements {
+            Ok(statements) => statements,
+            Err(parse_errors) => {
+                errors.extend(parse_errors.iter().map(|error| error.to_string()));
+                vec![]
+            }
+        };
+
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(RuntimeError::new(
+                ErrorKind::Import,
+                keyword,
+                &format!(
+                    "Error in module '{}':\n{}",
+                    path.display(),
+                    errors.join("\n")
+                ),
+            ))
+        }
+    }
+
+    //a new scope inside the current one
+    fn scope(&self) -> Rc<RefCell<Environment>> {
+        Rc::new(RefCell::new(Environment::with_enclosing(Rc::clone(
+            &self.environment,
+        ))))
+    }
+
+    //what a catch clause gets bound to, always an Error instance with a kind and a stack trace.
+    //errors from the interpreter become one, and so does any other thrown value, which is kept
+    //in its `value` field
+    fn caught(&self, error: RuntimeError) -> Value {
+        let stack = error
+            .stack_trace()
+            .into_iter()
+            .map(|line| Value::String(Rc::from(line)))
+            .collect();
+        let stack = Value::List(Rc::new(RefCell::new(stack)));
+
+        let instance = match error.value {
+            Some(Value::Instance(instance)) if instance.class.is_subclass_of(&self.error_class) => {
+                instance
+            }
+            thrown => {
+                let instance = Rc::new(LoxInstance::new(Rc::clone(&self.error_class)));
+                instance.set(
+                    Symbol::intern("message"),
+                    Value::String(Rc::from(error.message.as_str())),
+                );
+
+                match thrown {
+                    Some(value) => instance.set(Symbol::intern("value"), value),
+                    None => instance.set(
+                        Symbol::intern("kind"),
+                        Value::String(Rc::from(error.kind.to_string())),
+                    ),
+                }
+
+                instance
+            }
+        };
+
+        //a rethrown error keeps the trace from where it was first thrown
+        if instance.field(Symbol::intern("kind")).is_none() {
+            let kind = Value::String(Rc::clone(&instance.class.name));
+            instance.set(Symbol::intern("kind"), kind);
+        }
+
+        if instance.field(Symbol::intern("stack")).is_none() {
+            instance.set(Symbol::intern("stack"), stack);
+        }
+
+        Value::Instance(instance)
+    }
+
+    //a field of an Error instance, or None for any other value
+    fn error_field(&self, value: &Value, name: &str) -> Option<Value> {
+        match value {
+            Value::Instance(instance) if instance.class.is_subclass_of(&self.error_class) => {
+                instance.field(Symbol::intern(name))
+            }
+            _ => None,
+        }
     }
 
-    pub fn unary_expr(expr: Expr::Unary) -> Expr::Unary {
+    //runaway recursion is a runtime error like any other, so a script can catch it
+    pub fn call_value(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(
+                ErrorKind::StackOverflow,
+                paren,
+                "Stack overflow.",
+            ));
+        }
+
+        self.call_depth += 1;
+        //each lox call takes a lot of rust stack, debug builds especially, so the thread we're
+        //on can run out well before MAX_CALL_DEPTH. more is put on the heap when it runs low
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.call(callee, arguments, paren)
+        });
+        self.call_depth -= 1;
+
+        result
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        Self::check_call(&callee, arguments.len(), paren)?;
+
+        match callee {
+            Value::Function(function) => function.call(self, arguments, paren),
+            Value::Native(native) => native
+                .call(&arguments)
+                .map_err(|message| RuntimeError::new(ErrorKind::Runtime, paren, &message)),
+            //makes an instance and runs `init` on it, if there is one
+            Value::Class(class) => {
+                let instance = Rc::new(LoxInstance::new(Rc::clone(&class)));
+
+                if let Some(init) = class.find_method(Symbol::intern("init")) {
+                    init.bind(Rc::clone(&instance))
+                        .call(self, arguments, paren)?;
+                }
+
+                Ok(Value::Instance(instance))
+            }
+            Value::NativeClass(class) => class
+                .construct(&arguments)
+                .map_err(|message| RuntimeError::new(ErrorKind::Runtime, paren, &message)),
+            _ => unreachable!("check_call only lets functions and classes through"),
+        }
+    }
+
+    //whether `callee` can be called with that many arguments, found out before any of it runs
+    pub fn check_call(callee: &Value, count: usize, paren: &Token) -> Result<(), RuntimeError> {
+        let arity = match callee {
+            Value::Function(function) => Arity::Fixed(function.arity()),
+            Value::Native(native) => native.arity,
+            Value::Class(class) => Arity::Fixed(class.arity()),
+            Value::NativeClass(class) => class.arity().ok_or_else(|| {
+                RuntimeError::new(
+                    ErrorKind::Type,
+                    paren,
+                    &format!("Can't create '{}' instances from lox.", class.name),
+                )
+            })?,
+            _ => {
+                return Err(RuntimeError::new(
+                    ErrorKind::Type,
+                    paren,
+                    "Can only call functions and classes.",
+                ))
+            }
+        };
+
+        if !arity.accepts(count) {
+            return Err(RuntimeError::new(
+                ErrorKind::Arity,
+                paren,
+                &format!("Expected {arity} arguments but got {count}."),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn class(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Arc<Function>],
+    ) -> Result<(), RuntimeError> {
+        let superclass = match superclass {
+            Some(expr @ Expr::Variable(superclass_name, _)) => match self.evaluate(expr)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Type,
+                        superclass_name,
+                        "Superclass must be a class.",
+                    ))
+                }
+            },
+            Some(_) => unreachable!("the superclass is always a variable"),
+            None => None,
+        };
+
+        let symbol = name.symbol.expect("identifier tokens are always interned");
+        self.environment.borrow_mut().define(symbol, Value::Nil);
+
+        //methods of a subclass close over a scope that holds `super`
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                environment.define(Symbol::intern("super"), Value::Class(Rc::clone(superclass)));
+                Rc::new(RefCell::new(environment))
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let name = method.name.as_ref().expect("methods have names");
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+                let function = LoxFunction::method(Arc::clone(method), Rc::clone(&closure));
+
+                (symbol, Rc::new(function))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let class = LoxClass::new(Rc::from(name.lexeme.as_ref()), superclass, methods);
+        self.environment
+            .borrow_mut()
+            .assign(name, Value::Class(Rc::new(class)))
+    }
+
+    //what a pass through a loop body means for the loop. Ok(false) when a `break` aimed at
+    //this loop ended it. a `break` or `continue` naming some outer loop keeps unwinding
+    fn loop_body(label: Option<Symbol>, result: Result<(), Unwind>) -> Result<bool, Unwind> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(Unwind::Break(None)) => Ok(false),
+            Err(Unwind::Break(target)) if target == label => Ok(false),
+            Err(Unwind::Continue(None)) => Ok(true),
+            Err(Unwind::Continue(target)) if target == label => Ok(true),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    //the two variables of `for (a, b in ...)` take apart a two element list
+    fn unpack_pair(item: Value, keyword: &Token) -> Result<(Value, Value), RuntimeError> {
+        if let Value::List(elements) = &item {
+            if let [a, b] = &elements.borrow()[..] {
+                return Ok((a.clone(), b.clone()));
+            }
+        }
+
+        Err(RuntimeError::new(
+            ErrorKind::Type,
+            keyword,
+            "Can only unpack lists of two elements into two loop variables.",
+        ))
+    }
+
+    fn get_property(object: &Value, name: &Token) -> Result<Value, RuntimeError> {
+        match object {
+            Value::Module(module) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                module.get(symbol).ok_or_else(|| {
+                    RuntimeError::new(
+                        ErrorKind::Property,
+                        name,
+                        &format!(
+                            "Module '{}' has no export named '{}'.",
+                            module.name, name.lexeme
+                        ),
+                    )
+                })
+            }
+            Value::Instance(instance) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                instance
+                    .get(symbol)
+                    .ok_or_else(|| Self::undefined_property(name))
+            }
+            Value::NativeInstance(instance) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                match instance.get(symbol) {
+                    Ok(Some(value)) => Ok(value),
+                    Ok(None) => Err(Self::undefined_property(name)),
+                    Err(message) => Err(RuntimeError::new(ErrorKind::Runtime, name, &message)),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                name,
+                "Only instances have properties.",
+            )),
+        }
+    }
+
+    fn set_property(object: &Value, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        match object {
+            Value::Instance(instance) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+                instance.set(symbol, value);
+
+                Ok(())
+            }
+            //only properties with a setter can be assigned
+            Value::NativeInstance(instance) => {
+                let symbol = name.symbol.expect("identifier tokens are always interned");
+
+                match instance.set(symbol, value) {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err(RuntimeError::new(
+                        ErrorKind::Property,
+                        name,
+                        &format!("Can't set property '{}' on {instance}.", name.lexeme),
+                    )),
+                    Err(message) => Err(RuntimeError::new(ErrorKind::Runtime, name, &message)),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                name,
+                "Only instances have fields.",
+            )),
+        }
+    }
+
+    fn undefined_property(name: &Token) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::Property,
+            name,
+            &format!("Undefined property '{}'.", name.lexeme),
+        )
+    }
+
+    fn map_key(value: &Value, token: &Token) -> Result<MapKey, RuntimeError> {
+        map_key(value).map_err(|message| RuntimeError::new(ErrorKind::Type, token, &message))
+    }
+
+    fn get_index(object: &Value, index: &Value, bracket: &Token) -> Result<Value, RuntimeError> {
+        match object {
+            Value::List(elements) => {
+                let elements = elements.borrow();
+                let i = Self::list_index(index, elements.len(), bracket)?;
+
+                Ok(elements[i].clone())
+            }
+            Value::Map(map) => {
+                let key = Self::map_key(index, bracket)?;
+
+                map.borrow()
+                    .get(&key)
+                    .cloned()
+                    .ok_or_else(|| RuntimeError::new(ErrorKind::Index, bracket, &missing_key(&key)))
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                bracket,
+                "Only lists and maps can be indexed.",
+            )),
+        }
+    }
+
+    fn set_index(
+        object: &Value,
+        index: &Value,
+        value: Value,
+        bracket: &Token,
+    ) -> Result<(), RuntimeError> {
+        match object {
+            Value::List(elements) => {
+                let mut elements = elements.borrow_mut();
+                let i = Self::list_index(index, elements.len(), bracket)?;
+                elements[i] = value;
+
+                Ok(())
+            }
+            //inserts the key if it isn't there yet
+            Value::Map(map) => {
+                let key = Self::map_key(index, bracket)?;
+                map.borrow_mut().insert(key, value);
+
+                Ok(())
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                bracket,
+                "Only lists and maps can be indexed.",
+            )),
+        }
+    }
+
+    //a missing start means the beginning, a missing end means the end. the slice is a new list
+    fn slice(
+        object: &Value,
+        start: Option<Value>,
+        end: Option<Value>,
+        bracket: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let elements = match object {
+            Value::List(elements) => elements.borrow(),
+            _ => {
+                return Err(RuntimeError::new(
+                    ErrorKind::Type,
+                    bracket,
+                    "Only lists can be sliced.",
+                ))
+            }
+        };
+
+        let start = match start {
+            Some(start) => Self::slice_bound(&start, elements.len(), bracket)?,
+            None => 0,
+        };
+        let end = match end {
+            Some(end) => Self::slice_bound(&end, elements.len(), bracket)?,
+            None => elements.len(),
+        };
+
+        if start > end {
+            return Err(RuntimeError::new(
+                ErrorKind::Index,
+                bracket,
+                &format!("Slice start {start} is after its end {end}."),
+            ));
+        }
+
+        Ok(Value::List(Rc::new(RefCell::new(
+            elements[start..end].to_vec(),
+        ))))
+    }
+
+    fn list_index(index: &Value, len: usize, bracket: &Token) -> Result<usize, RuntimeError> {
+        let i = Self::integer_index(index, bracket)?;
+
+        if i < 0 || i as usize >= len {
+            return Err(RuntimeError::new(
+                ErrorKind::Index,
+                bracket,
+                &format!("Index {i} is out of bounds for a list of length {len}."),
+            ));
+        }
+
+        Ok(i as usize)
+    }
+
+    //unlike an index, a slice bound can be one past the last element
+    fn slice_bound(bound: &Value, len: usize, bracket: &Token) -> Result<usize, RuntimeError> {
+        let i = Self::integer_index(bound, bracket)?;
+
+        if i < 0 || i as usize > len {
+            return Err(RuntimeError::new(
+                ErrorKind::Index,
+                bracket,
+                &format!("Slice bound {i} is out of bounds for a list of length {len}."),
+            ));
+        }
+
+        Ok(i as usize)
+    }
+
+    fn integer_index(index: &Value, bracket: &Token) -> Result<i64, RuntimeError> {
+        match index {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 => Ok(*n as i64),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                bracket,
+                "Index must be an integer.",
+            )),
+        }
+    }
 
+    //bitwise operators work on the i64 a number holds, as long as it holds one exactly
+    fn integer_operand(value: &Value, token: &Token) -> Result<i64, RuntimeError> {
+        match value {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 => Ok(*n as i64),
+            Value::Number(_) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                token,
+                "Operands of bitwise operators must be integers.",
+            )),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                token,
+                "Operands must be numbers.",
+            )),
+        }
     }
 
-    //TODO: trying pattern matching in fn signature here
-    pub fn grouping_expr(expr(expression): Expr::Grouping) -> Expr::Grouping {
-        evaluate(expression)
+    fn bitwise(operator: Operator, a: i64, b: i64, token: &Token) -> Result<i64, RuntimeError> {
+        match operator {
+            Operator::BitAnd => Ok(a & b),
+            Operator::BitOr => Ok(a | b),
+            Operator::BitXor => Ok(a ^ b),
+            Operator::ShiftLeft | Operator::ShiftRight if !(0..64).contains(&b) => {
+                Err(RuntimeError::new(
+                    ErrorKind::Runtime,
+                    token,
+                    "Shift amount must be between 0 and 63.",
+                ))
+            }
+            Operator::ShiftLeft => Ok(a << b),
+            //arithmetic shift, the sign is kept
+            Operator::ShiftRight => Ok(a >> b),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Runtime,
+                token,
+                "Invalid bitwise operator.",
+            )),
+        }
     }
 
-    fn evaluate<T>(&self, expr: Expr) -> T {
-        expr.accept(self)
+    fn literal_value(literal: &Literal) -> Value {
+        match literal {
+            Literal::Number(n) => Value::Number(*n),
+            Literal::String(s) => Value::String(Rc::from(s.as_str())),
+            Literal::True => Value::Bool(true),
+            Literal::False => Value::Bool(false),
+            Literal::Nil | Literal::Expr(_) => Value::Nil,
+        }
     }
 }
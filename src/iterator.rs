@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::interpreter::*;
+use crate::map::*;
+use crate::symbol::*;
+use crate::token::*;
+use crate::value::*;
+
+//the numbers from `start` up to but not including `end`, `step` apart. made by `range()`
+//and never materialised, so a huge range costs nothing until it's iterated
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LoxRange {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl LoxRange {
+    pub fn len(&self) -> usize {
+        let len = ((self.end - self.start) / self.step).ceil();
+
+        if len > 0.0 {
+            len as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<f64> {
+        if i < self.len() {
+            Some(self.start + i as f64 * self.step)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for LoxRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "range({}, {}, {})", self.start, self.end, self.step)
+    }
+}
+
+//where a for-in loop is up to. the native collections keep a position, so elements added to
+//a list while it's being iterated are picked up. anything else is asked for its next element
+pub enum LoxIterator {
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    //gives [key, value] lists when the flag is set, otherwise just keys
+    Map(Rc<RefCell<LoxMap>>, usize, bool),
+    //one character at a time, the position is a byte offset
+    String(Rc<str>, usize),
+    Range(LoxRange, usize),
+    //an object with a `next` method. if it has a `hasNext` method too, that says when it's
+    //done and `next` can give back nil, otherwise nil from `next` is what ends the loop
+    Object(Value),
+}
+
+impl LoxIterator {
+    //user classes take part by having an `iter` method that gives back the iterator object
+    pub fn new(
+        interpreter: &mut Interpreter,
+        iterable: Value,
+        pairs: bool,
+        token: &Token,
+    ) -> Result<Self, RuntimeError> {
+        match iterable {
+            Value::List(elements) => Ok(LoxIterator::List(elements, 0)),
+            Value::Map(map) => Ok(LoxIterator::Map(map, 0, pairs)),
+            Value::String(s) => Ok(LoxIterator::String(s, 0)),
+            Value::Range(range) => Ok(LoxIterator::Range(range, 0)),
+            Value::Instance(instance) => match instance.get(Symbol::intern("iter")) {
+                Some(iter) => Ok(LoxIterator::Object(interpreter.call_value(
+                    iter,
+                    vec![],
+                    token,
+                )?)),
+                None => Err(Self::not_iterable(token)),
+            },
+            _ => Err(Self::not_iterable(token)),
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        token: &Token,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let next = match self {
+            LoxIterator::List(elements, i) => {
+                let next = elements.borrow().get(*i).cloned();
+                *i += 1;
+                next
+            }
+            LoxIterator::Map(map, i, pairs) => {
+                let next = map.borrow().entry(*i).map(|(key, value)| {
+                    if *pairs {
+                        Value::List(Rc::new(RefCell::new(vec![key.to_value(), value.clone()])))
+                    } else {
+                        key.to_value()
+                    }
+                });
+                *i += 1;
+                next
+            }
+            LoxIterator::String(s, i) => {
+                let next = s[*i..].chars().next();
+
+                next.map(|c| {
+                    *i += c.len_utf8();
+                    Value::String(Rc::from(c.to_string()))
+                })
+            }
+            LoxIterator::Range(range, i) => {
+                let next = range.get(*i).map(Value::Number);
+                *i += 1;
+                next
+            }
+            LoxIterator::Object(iterator) => {
+                let method = |name| match &*iterator {
+                    Value::Instance(instance) => instance.get(Symbol::intern(name)),
+                    _ => None,
+                };
+
+                let next = method("next").ok_or_else(|| {
+                    RuntimeError::new(
+                        ErrorKind::Type,
+                        token,
+                        "Iterator must have a 'next' method.",
+                    )
+                })?;
+
+                match method("hasNext") {
+                    Some(has_next) => {
+                        if interpreter.call_value(has_next, vec![], token)?.is_truthy() {
+                            Some(interpreter.call_value(next, vec![], token)?)
+                        } else {
+                            None
+                        }
+                    }
+                    None => match interpreter.call_value(next, vec![], token)? {
+                        Value::Nil => None,
+                        value => Some(value),
+                    },
+                }
+            }
+        };
+
+        Ok(next)
+    }
+
+    fn not_iterable(token: &Token) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::Type,
+            token,
+            "Can only iterate over lists, maps, strings, ranges and objects with an 'iter' method.",
+        )
+    }
+}
@@ -5,33 +5,91 @@
 // use std::io::stdin;
 // use std::io::BufReader;
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process;
 
+use clap::Parser;
+
 // use crate::scanner::*;
 use crate::lox::*;
 
+pub mod builtins;
+pub mod class;
+pub mod environment;
 pub mod expr;
+pub mod function;
+pub mod golden;
+pub mod interpreter;
+pub mod iterator;
 pub mod lox;
+pub mod map;
+pub mod module;
+pub mod native;
+pub mod native_class;
+pub mod output;
 pub mod parser;
 pub mod scanner;
+pub mod stmt;
+pub mod symbol;
 pub mod token;
 pub mod tokentype;
+pub mod value;
+
+//command line arguments
+#[derive(clap::Parser, Debug)]
+#[clap(about = "A Lox interpreter. Runs a script, or starts a REPL without one")]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Directory to search for imported modules, before LOX_PATH. Can be given more than once
+    #[clap(long = "lib-path", value_name = "DIR")]
+    lib_paths: Vec<PathBuf>,
+
+    /// The script to run
+    script: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Run the .lox scripts under each directory and check them against their `// expect` comments
+    Test {
+        #[clap(
+            default_values = &["tests/lox", "tests/craftinginterpreters"],
+            value_name = "DIR"
+        )]
+        dirs: Vec<PathBuf>,
+    },
+}
 
 pub fn init() {
-    let args: Vec<String> = env::args().collect();
+    let args = Args::parse();
 
-    if args.len() > 2 {
-        eprintln!("Too many args");
+    if let Some(Command::Test { dirs }) = args.command {
+        //every directory is run, even after one fails
+        let failed = dirs
+            .iter()
+            .fold(0, |failed, dir| failed.max(run_tests(dir)));
+        process::exit(failed);
+    }
 
-        process::exit(1);
-    } else if args.len() == 2 {
-        let file_path = &args[1];
+    let mut lox = Lox::new();
+
+    //--lib-path directories are searched first, then the ones in LOX_PATH
+    for dir in &args.lib_paths {
+        lox.add_lib_path(dir);
+    }
 
-        if let '/' = file_path.chars().next().unwrap() {
-            let lox = Lox::new();
-            lox.run_file(&file_path);
-            println!("running file...");
-        } else {
+    if let Some(lox_path) = env::var_os("LOX_PATH") {
+        for dir in env::split_paths(&lox_path) {
+            lox.add_lib_path(&dir);
+        }
+    }
+
+    match args.script {
+        Some(file_path) if file_path.is_absolute() => lox.run_file(&file_path.to_string_lossy()),
+        Some(file_path) => {
             let mut current_path = env::current_dir().unwrap_or_else(|err| {
                 eprintln!("error getting current_dir with error: {}", err);
                 process::exit(1);
@@ -48,14 +106,26 @@ pub fn init() {
                     process::exit(1);
                 });
 
-            let lox = Lox::new();
             lox.run_file(&absolute_path);
         }
-        // let bytes = contents.as_bytes();
-        // io::stdout().write(&bytes);
-        // println!("Text in provided file:\n{contents}");
-    } else {
-        let mut lox = Lox::new();
-        lox.run_prompt(); //interactive lox CLI
+        None => lox.run_prompt(), //interactive lox CLI
+    }
+}
+
+//prints the unexpected failures and a summary by category. the exit code is 1 if anything
+//didn't do what known_failures.txt says it would
+fn run_tests(dir: &Path) -> i32 {
+    let suite = env::current_exe().and_then(|lox| golden::Suite::run(&lox, dir));
+
+    match suite {
+        Ok(suite) => {
+            println!("{}:", dir.display());
+            print!("{}", suite.report());
+            i32::from(!suite.as_expected())
+        }
+        Err(err) => {
+            eprintln!("Can't run the tests in {}: {err}", dir.display());
+            1
+        }
     }
 }
@@ -1,79 +1,274 @@
-use std::fs;
-use std::io::stdin;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 use std::process;
 
+use crate::interpreter::*;
+use crate::native::*;
+use crate::native_class::*;
+use crate::output::*;
 use crate::parser::*;
 use crate::scanner::*;
+use crate::symbol::*;
 use crate::token::*;
 use crate::tokentype::*;
+use crate::value::*;
 
 #[derive(Debug)]
 pub struct Lox {
     pub had_error: bool,
+    pub had_runtime_error: bool,
+    //kept between runs so the REPL remembers state from line to line
+    interpreter: Interpreter,
+    //where errors and warnings are reported
+    diagnostics: Output,
+}
+
+//why a call into lox from rust failed
+#[derive(Debug)]
+pub enum LoxError {
+    //the source didn't scan or parse. one message per error, worded as `lox` would print them
+    Compile(Vec<String>),
+    Runtime(RuntimeError),
+    //a lox value wasn't the type the rust side asked for
+    Conversion(String),
+    //call_function couldn't make the call at all, e.g. there's no global by that name or it
+    //got the wrong number of arguments. there's no line in a script to point at for these
+    Call(String),
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::Compile(errors) => write!(f, "{}", errors.join("\n")),
+            LoxError::Runtime(err) => write!(f, "{err}"),
+            LoxError::Conversion(message) | LoxError::Call(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+impl From<RuntimeError> for LoxError {
+    fn from(err: RuntimeError) -> Self {
+        LoxError::Runtime(err)
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lox {
     pub fn new() -> Self {
-        Self { had_error: false }
+        Self {
+            had_error: false,
+            had_runtime_error: false,
+            interpreter: Interpreter::new(),
+            diagnostics: Output::stderr(),
+        }
     }
 
-    pub fn run_file(&self, file_path: &str) {
-        println!("opening file...");
-        // let file = File::open(&file_path).unwrap_or_else(|err| {
-        //     eprintln!("{err}");
-        //     process::exit(1);
-        // });
+    pub fn run_file(&mut self, file_path: &str) {
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                let _ = writeln!(self.diagnostics, "{err}");
+                process::exit(1);
+            }
+        };
 
-        let file_source = fs::read_to_string(&file_path).unwrap_or_else(|err| {
-            eprintln!("{err}");
-            process::exit(1);
-        });
+        //imports in the file are relative to it
+        self.interpreter.set_script_path(Path::new(file_path));
 
-        // let reader = BufReader::new(&file);
+        //the scanner reads the file in chunks as the parser asks for tokens
+        self.run_scanner(Scanner::from_reader(file));
 
-        // for line in reader.lines() {
-        //     println!("here's a line: {}", line.expect("error in run_file"));
-        // }
-
-        self.run(&file_source);
+        //exiting doesn't drop the writers, so anything they've buffered has to go out now
+        self.interpreter.flush_output();
+        let _ = self.diagnostics.flush();
 
         if self.had_error {
-            process::exit(1);
+            process::exit(65);
+        }
+
+        if self.had_runtime_error {
+            process::exit(70);
+        }
+    }
+
+    //defines a global function implemented in rust, e.g.
+    //lox.define_native("double", Arity::Fixed(1), |args| match args[0] { ... })
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.interpreter.define_native(name, arity, function);
+    }
+
+    //defines a global class backed by a rust type. see NativeClass
+    pub fn define_class<T: NativeClass>(&mut self) {
+        self.interpreter.define_class::<T>();
+    }
+
+    //wraps a rust value for scripts, e.g. to pass to call_function or set_global. get it back
+    //out of a Value::NativeInstance with borrow or borrow_mut
+    pub fn native_instance<T: NativeClass>(&mut self, value: T) -> Value {
+        self.interpreter.native_instance(value)
+    }
+
+    //adds a directory that imports by bare name are searched for in
+    pub fn add_lib_path(&mut self, dir: &Path) {
+        self.interpreter.add_search_path(dir);
+    }
+
+    //runs source and gives back the value of its last statement, if that's an expression
+    //statement (whose `;` can be left off), or nil. errors come back to the caller instead
+    //of going to the diagnostics
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let mut parser = Parser::from_scanner(Scanner::new(source));
+        parser.allow_trailing_expression();
+        let statements = parser.parse();
+
+        let mut errors: Vec<String> = parser
+            .scan_errors()
+            .iter()
+            .map(|error| format!("[line {}] Error: {}", error.line, error.message))
+            .collect();
+
+        match statements {
+            Ok(statements) if errors.is_empty() => Ok(self.interpreter.eval(&statements)?),
+            Ok(_) => Err(LoxError::Compile(errors)),
+            Err(parse_errors) => {
+                errors.extend(parse_errors.iter().map(|err| err.to_string()));
+                Err(LoxError::Compile(errors))
+            }
         }
     }
 
-    //TODO: fix run_prompt
+    //the value of a global variable (or builtin), if there is one by that name
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter
+            .globals
+            .borrow()
+            .lookup(Symbol::intern(name))
+    }
+
+    //defines a global variable, replacing any already there
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.interpreter
+            .globals
+            .borrow_mut()
+            .define(Symbol::intern(name), value.into());
+    }
+
+    //calls the global function (or class) by that name. finding it, checking it can be called
+    //and checking the number of arguments fail with a LoxError::Call, anything that goes wrong
+    //once it's running is a LoxError::Runtime
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, LoxError> {
+        let token = Token::new(Box::new(TokenType::Identifier), name, "", 0);
+
+        let callee = self
+            .interpreter
+            .globals
+            .borrow()
+            .get(&token)
+            .map_err(|err| LoxError::Call(err.message))?;
+
+        Interpreter::check_call(&callee, arguments.len(), &token)
+            .map_err(|err| LoxError::Call(err.message))?;
+
+        self.interpreter
+            .call_value(callee, arguments, &token)
+            .map_err(LoxError::Runtime)
+    }
+
+    //where `print` writes to. stdout unless this is called
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.interpreter.set_output(Output::new(output));
+    }
+
+    //where errors and warnings are reported. stderr unless this is called
+    pub fn set_diagnostics(&mut self, diagnostics: impl Write + 'static) {
+        self.diagnostics = Output::new(diagnostics);
+    }
+
     pub fn run_prompt(&mut self) {
-        let mut buffer = String::new();
-        // let f = File::open(); need a stream to get the line?
-        // let mut reader = BufReader::new();
+        //the prompt is for whoever's typing, so it always goes to the terminal
+        self.repl(io::stdin().lock(), io::stdout());
+    }
+
+    fn repl(&mut self, input: impl BufRead, mut prompt: impl Write) {
+        let mut lines = input.lines();
 
         loop {
-            print!("> ");
-            // let line = stdin().read_line(&mut buffer).unwrap(); //buffer contains the value of the line, and line is just the line num
-            // if line == null { break; }
-            self.run(&buffer);
+            let _ = write!(prompt, "> ");
+            let _ = prompt.flush();
+
+            //ctrl-d (or a read error) ends the session
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => break,
+            };
+
+            //each line succeeds or fails on its own
             self.had_error = false;
+            self.had_runtime_error = false;
+
+            self.run(&line);
         }
     }
 
-    pub fn run(&self, source: &str) {
-        let scanner = Scanner::new(&source);
-        let tokens = scanner.scan_tokens();
+    pub fn run(&mut self, source: &str) {
+        self.run_scanner(Scanner::new(source));
+    }
 
+    fn run_scanner(&mut self, scanner: Scanner) {
         //code to print scanned tokens, commented out bc not necessary
-        // for token in &tokens {
+        // for token in scanner {
         //     println!("Printing token: {:?}", token);
         // }
 
-        let mut parser = Parser::new(tokens);
-        let expression = parser.parse();
+        let mut parser = Parser::from_scanner(scanner);
+        let statements = parser.parse();
+
+        for error in parser.scan_errors().to_vec() {
+            self.report(error.line, "", &error.message);
+        }
+
+        //warnings don't stop the program from running
+        for warning in parser.warnings() {
+            let _ = writeln!(
+                self.diagnostics,
+                "[line {}] Warning{}: {}",
+                warning.line, warning.location, warning.message
+            );
+        }
+
+        let statements = match statements {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for err in errors {
+                    let _ = writeln!(self.diagnostics, "{err}");
+                }
+
+                self.had_error = true;
+                return;
+            }
+        };
 
         if self.had_error {
             return;
         }
 
-        // println!("Here he inits the AstPrinter with expression: {}", expression);
+        if let Err(err) = self.interpreter.interpret(&statements) {
+            self.runtime_error(&err);
+        }
     }
 
     //TODO: maybe have line: Option<u32>?
@@ -83,7 +278,7 @@ impl Lox {
 
     //TODO: make report a macro to avoid the messy formatting as in error() below
     fn report(&mut self, line: u32, location: &str, message: &str) {
-        eprintln!("[line {line}] Error {location} {message}");
+        let _ = writeln!(self.diagnostics, "[line {line}] Error{location}: {message}");
         self.had_error = true;
     }
 
@@ -91,7 +286,236 @@ impl Lox {
         if *token.token_type == TokenType::Eof {
             self.report(token.line, " at end", message);
         } else {
-            self.report(token.line, "", message);
+            self.report(token.line, &format!(" at '{}'", token.lexeme), message);
+        }
+    }
+
+    pub fn runtime_error(&mut self, error: &RuntimeError) {
+        let _ = writeln!(self.diagnostics, "{error}");
+        self.had_runtime_error = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //output and diagnostics are kept out of the test runner's
+    fn lox() -> (Lox, SharedBuffer) {
+        let output = SharedBuffer::new();
+        let mut lox = Lox::new();
+
+        lox.set_output(output.clone());
+        lox.set_diagnostics(SharedBuffer::new());
+
+        (lox, output)
+    }
+
+    fn eval(lox: &mut Lox, source: &str) -> Value {
+        lox.eval(source)
+            .unwrap_or_else(|err| panic!("{source:?} failed: {err}"))
+    }
+
+    //everything lox prints goes to the writers it was given, and nothing goes to stdout/stderr
+    #[test]
+    fn run_writes_to_the_configured_writers() {
+        let output = SharedBuffer::new();
+        let diagnostics = SharedBuffer::new();
+        let mut lox = Lox::new();
+
+        lox.set_output(output.clone());
+        lox.set_diagnostics(diagnostics.clone());
+
+        lox.run("print \"hello\";\nmatch (1) { 1 => print 1; }");
+        assert_eq!(output.take(), "hello\n1\n");
+        assert_eq!(
+            diagnostics.take(),
+            "[line 2] Warning at 'match': Match has no catch-all arm, so some values won't match any arm.\n"
+        );
+        assert!(!lox.had_error);
+
+        lox.run("print 1 +;\n@");
+        assert_eq!(output.take(), "");
+        assert_eq!(
+            diagnostics.take(),
+            "[line 2] Error: Unexpected character '@'.\n[line 1] Error at ';': Expect expression.\n"
+        );
+        assert!(lox.had_error);
+
+        lox.had_error = false;
+        lox.run("print 1;\nprint nil.x;\nprint 2;");
+        assert_eq!(output.take(), "1\n");
+        assert_eq!(
+            diagnostics.take(),
+            "Only instances have properties.\n[line 2]\n"
+        );
+        assert!(lox.had_runtime_error);
+    }
+
+    #[test]
+    fn eval_gives_back_the_last_expression() {
+        let (mut lox, output) = lox();
+
+        assert_eq!(eval(&mut lox, "1 + 2"), Value::Number(3.0));
+        assert_eq!(eval(&mut lox, "1 + 2;"), Value::Number(3.0));
+        assert_eq!(eval(&mut lox, "var a = 1;"), Value::Nil);
+        assert_eq!(eval(&mut lox, "print a; a * 10"), Value::Number(10.0));
+        assert_eq!(eval(&mut lox, "\"a\" + \"b\""), Value::String("ab".into()));
+        assert_eq!(output.take(), "1\n");
+    }
+
+    #[test]
+    fn eval_keeps_state() {
+        let (mut lox, _) = lox();
+
+        eval(
+            &mut lox,
+            "var count = 0; fun bump() { count = count + 1; return count; }",
+        );
+        eval(&mut lox, "bump(); bump();");
+
+        assert_eq!(eval(&mut lox, "count"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn eval_compile_errors() {
+        let (mut lox, _) = lox();
+
+        match lox.eval("var = 1; 1 +; @") {
+            Err(LoxError::Compile(errors)) => assert_eq!(
+                errors,
+                [
+                    "[line 1] Error: Unexpected character '@'.",
+                    "[line 1] Error at '=': Expect variable name.",
+                    "[line 1] Error at ';': Expect expression.",
+                ]
+            ),
+            other => panic!("expected compile errors, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn eval_runtime_errors() {
+        let (mut lox, _) = lox();
+
+        match lox.eval("var a = 1;\n-\"x\"") {
+            Err(LoxError::Runtime(err)) => {
+                assert_eq!(err.message, "Operand must be a number.");
+                assert_eq!(err.line, 2);
+            }
+            other => panic!("expected a runtime error, got {other:?}"),
+        }
+
+        //a failed eval doesn't undo what ran before the error
+        assert_eq!(eval(&mut lox, "a"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn globals() {
+        let (mut lox, _) = lox();
+
+        assert_eq!(lox.get_global("missing"), None);
+
+        lox.set_global("limit", 10);
+        lox.set_global("names", vec!["a", "b"]);
+
+        assert_eq!(eval(&mut lox, "limit * 2"), Value::Number(20.0));
+        assert_eq!(eval(&mut lox, "names[1]").to_string(), "b");
+
+        eval(&mut lox, "var greeting = \"hi\";");
+        let greeting = lox.get_global("greeting").expect("defined by the script");
+        assert_eq!(String::try_from(greeting).unwrap(), "hi");
+
+        //builtins are globals too
+        assert!(lox.get_global("clock").is_some());
+    }
+
+    #[test]
+    fn repl_lines_fail_on_their_own() {
+        let (mut lox, output) = lox();
+
+        lox.repl(
+            "print 1 +;\nprint nil.x;\nvar a = 2;\nprint a;\n".as_bytes(),
+            io::sink(),
+        );
+
+        assert_eq!(output.take(), "2\n");
+        assert!(!lox.had_error);
+        assert!(!lox.had_runtime_error);
+
+        lox.repl("print nil.x;\n".as_bytes(), io::sink());
+        assert!(lox.had_runtime_error);
+
+        lox.repl("print a;\n".as_bytes(), io::sink());
+        assert!(!lox.had_runtime_error);
+        assert_eq!(output.take(), "2\n");
+    }
+
+    #[test]
+    fn call_function() {
+        let (mut lox, _) = lox();
+
+        eval(
+            &mut lox,
+            "fun add(a, b) { return a + b; } class Point { init(x) { this.x = x; } }",
+        );
+
+        let sum = lox.call_function("add", vec![1.into(), 2.into()]).unwrap();
+        assert_eq!(f64::try_from(sum).unwrap(), 3.0);
+
+        let point = lox.call_function("Point", vec![5.into()]).unwrap();
+        assert_eq!(point.to_string(), "Point instance");
+    }
+
+    fn call_error(lox: &mut Lox, name: &str, arguments: Vec<Value>) -> LoxError {
+        match lox.call_function(name, arguments) {
+            Ok(value) => panic!("calling {name} gave back {value}"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn call_function_errors() {
+        let (mut lox, _) = lox();
+
+        eval(
+            &mut lox,
+            "var number = 1;\nfun one(a) { return a; }\nfun fails() {\n  return nil.field;\n}",
+        );
+
+        //the call itself failing has no line in a script to report
+        assert!(matches!(
+            call_error(&mut lox, "missing", vec![]),
+            LoxError::Call(message) if message == "Undefined variable 'missing'."
+        ));
+        assert!(matches!(
+            call_error(&mut lox, "number", vec![]),
+            LoxError::Call(message) if message == "Can only call functions and classes."
+        ));
+        assert!(matches!(
+            call_error(&mut lox, "one", vec![]),
+            LoxError::Call(message) if message == "Expected 1 arguments but got 0."
+        ));
+
+        //an error inside the function has the line it happened on
+        match call_error(&mut lox, "fails", vec![]) {
+            LoxError::Runtime(err) => {
+                assert_eq!(err.message, "Only instances have properties.");
+                assert_eq!(err.line, 4);
+            }
+            other => panic!("expected a runtime error, got {other:?}"),
+        }
+
+        //and so does an error from a native called directly, though it has no line to report
+        lox.define_native("broken", Arity::Fixed(0), |_| Err("It broke.".to_string()));
+
+        match call_error(&mut lox, "broken", vec![]) {
+            LoxError::Runtime(err) => assert_eq!(err.message, "It broke."),
+            other => panic!("expected a runtime error, got {other:?}"),
         }
+        assert!(matches!(
+            call_error(&mut lox, "broken", vec![1.into()]),
+            LoxError::Call(message) if message == "Expected 0 arguments but got 1."
+        ));
     }
 }
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::value::*;
+
+//what a map can be keyed by. numbers are stored by their bits, with -0 folded into 0 so that
+//keys compare the way `==` does
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Bool(bool),
+    Number(u64),
+    String(Rc<str>),
+}
+
+impl MapKey {
+    //None for values that can't be keys. NaN isn't equal to itself, so it could never be found
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(MapKey::Nil),
+            Value::Bool(b) => Some(MapKey::Bool(*b)),
+            Value::Number(n) if n.is_nan() => None,
+            Value::Number(n) if *n == 0.0 => Some(MapKey::Number(0.0f64.to_bits())),
+            Value::Number(n) => Some(MapKey::Number(n.to_bits())),
+            Value::String(s) => Some(MapKey::String(Rc::clone(s))),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Nil => Value::Nil,
+            MapKey::Bool(b) => Value::Bool(*b),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => Value::String(Rc::clone(s)),
+        }
+    }
+}
+
+//a map that remembers the order keys were first inserted in
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Value)>,
+    //where each key's entry is in `entries`
+    indexes: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.indexes.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.indexes.contains_key(key)
+    }
+
+    //replacing the value of an existing key keeps the key where it was
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.indexes.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indexes.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    //the entries after the removed one shift down, so the order of the rest is kept
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let i = self.indexes.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+
+        for (key, _) in &self.entries[i..] {
+            if let Some(index) = self.indexes.get_mut(key) {
+                *index -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    //the entry at position `i` in insertion order
+    pub fn entry(&self, i: usize) -> Option<(&MapKey, &Value)> {
+        self.entries.get(i).map(|(key, value)| (key, value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
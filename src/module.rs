@@ -0,0 +1,49 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::environment::*;
+use crate::symbol::*;
+use crate::value::*;
+
+//a loaded .lox file. it keeps its own global scope, and only the names it exports can be
+//reached from outside
+pub struct LoxModule {
+    //the path it was imported by, for messages
+    pub name: Rc<str>,
+    globals: Rc<RefCell<Environment>>,
+    exports: HashSet<Symbol>,
+}
+
+impl LoxModule {
+    pub fn new(name: Rc<str>, globals: Rc<RefCell<Environment>>, exports: HashSet<Symbol>) -> Self {
+        Self {
+            name,
+            globals,
+            exports,
+        }
+    }
+
+    //exports are live, so a module that changes an exported variable is seen to change it
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        if !self.exports.contains(&name) {
+            return None;
+        }
+
+        self.globals.borrow().lookup(name)
+    }
+}
+
+//the globals can hold functions that close over them, so printing them could recurse forever
+impl fmt::Debug for LoxModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxModule({self})")
+    }
+}
+
+impl fmt::Display for LoxModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use crate::value::*;
+
+//how many arguments a native function takes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    //anything from the first to the second, inclusive
+    Range(usize, usize),
+    //at least this many, with no upper limit
+    Variadic(usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Fixed(n) => count == n,
+            Arity::Range(min, max) => (min..=max).contains(&count),
+            Arity::Variadic(min) => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Fixed(n) => write!(f, "{n}"),
+            Arity::Range(min, max) => write!(f, "{min} to {max}"),
+            Arity::Variadic(min) => write!(f, "at least {min}"),
+        }
+    }
+}
+
+//the rust side of a native. an Err is raised as a runtime error at the call, with the
+//string as its message
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+//a function implemented in rust that scripts call like any other
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    //the arity has already been checked
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, String> {
+        (self.function)(arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction({}, {:?})", self.name, self.arity)
+    }
+}
+
+//printed like jlox does. Debug has the name, for when it matters which native this is
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
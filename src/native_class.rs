@@ -0,0 +1,430 @@
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::native::*;
+use crate::symbol::*;
+use crate::value::*;
+
+//a rust type that scripts can use as a class, e.g.
+//
+//impl NativeClass for Account {
+//    const NAME: &'static str = "Account";
+//
+//    fn define(class: &mut NativeClassBuilder<Self>) {
+//        class
+//            .init(Arity::Fixed(1), |args| ...)
+//            .getter("balance", |account| Value::Number(account.balance))
+//            .method("deposit", Arity::Fixed(1), |account, args| ...);
+//    }
+//}
+pub trait NativeClass: Sized + 'static {
+    //the class's name in lox
+    const NAME: &'static str;
+
+    //declares the constructor, methods and properties scripts can use
+    fn define(class: &mut NativeClassBuilder<Self>);
+}
+
+type Constructor = dyn Fn(&[Value]) -> Result<Box<dyn Any>, String>;
+type Method = dyn Fn(&mut dyn Any, &[Value]) -> Result<Value, String>;
+type Getter = dyn Fn(&dyn Any) -> Value;
+type Setter = dyn Fn(&mut dyn Any, Value) -> Result<(), String>;
+
+//the lox side of a NativeClass. the closures take the wrapped value as `dyn Any`, and the
+//builder downcasts it back to the type they were written for
+pub struct LoxNativeClass {
+    pub name: Rc<str>,
+    init: Option<(Arity, Box<Constructor>)>,
+    methods: HashMap<Symbol, (Arity, Rc<Method>)>,
+    getters: HashMap<Symbol, Box<Getter>>,
+    setters: HashMap<Symbol, Box<Setter>>,
+}
+
+impl LoxNativeClass {
+    pub fn new<T: NativeClass>() -> Self {
+        let mut builder = NativeClassBuilder {
+            class: Self {
+                name: T::NAME.into(),
+                init: None,
+                methods: HashMap::new(),
+                getters: HashMap::new(),
+                setters: HashMap::new(),
+            },
+            marker: PhantomData,
+        };
+
+        T::define(&mut builder);
+        builder.class
+    }
+
+    //None if the class has no constructor, so scripts can't call it
+    pub fn arity(&self) -> Option<Arity> {
+        self.init.as_ref().map(|(arity, _)| *arity)
+    }
+
+    //the class has a constructor and the arity has already been checked
+    pub fn construct(self: &Rc<Self>, arguments: &[Value]) -> Result<Value, String> {
+        let (_, init) = self.init.as_ref().expect("class has a constructor");
+
+        Ok(Value::NativeInstance(Rc::new(LoxNativeInstance {
+            class: Rc::clone(self),
+            value: RefCell::new(init(arguments)?),
+        })))
+    }
+}
+
+impl fmt::Debug for LoxNativeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxNativeClass({})", self.name)
+    }
+}
+
+impl fmt::Display for LoxNativeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+//fills in a LoxNativeClass from closures written against T
+pub struct NativeClassBuilder<T> {
+    class: LoxNativeClass,
+    marker: PhantomData<T>,
+}
+
+impl<T: NativeClass> NativeClassBuilder<T> {
+    //what calling the class does. without one, instances can only come from rust
+    pub fn init(
+        &mut self,
+        arity: Arity,
+        init: impl Fn(&[Value]) -> Result<T, String> + 'static,
+    ) -> &mut Self {
+        self.class.init = Some((
+            arity,
+            Box::new(move |args| Ok(Box::new(init(args)?) as Box<dyn Any>)),
+        ));
+        self
+    }
+
+    pub fn method(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        method: impl Fn(&mut T, &[Value]) -> Result<Value, String> + 'static,
+    ) -> &mut Self {
+        self.class.methods.insert(
+            Symbol::intern(name),
+            (
+                arity,
+                Rc::new(move |this, args| method(downcast_mut(this), args)),
+            ),
+        );
+        self
+    }
+
+    //a property scripts can read with `object.name`
+    pub fn getter(&mut self, name: &str, getter: impl Fn(&T) -> Value + 'static) -> &mut Self {
+        self.class.getters.insert(
+            Symbol::intern(name),
+            Box::new(move |this| getter(downcast_ref(this))),
+        );
+        self
+    }
+
+    //a property scripts can assign with `object.name = value`. an Err is raised as a
+    //runtime error at the assignment
+    pub fn setter(
+        &mut self,
+        name: &str,
+        setter: impl Fn(&mut T, Value) -> Result<(), String> + 'static,
+    ) -> &mut Self {
+        self.class.setters.insert(
+            Symbol::intern(name),
+            Box::new(move |this, value| setter(downcast_mut(this), value)),
+        );
+        self
+    }
+}
+
+//a class's closures are only ever handed values its own constructor made
+fn downcast_ref<T: 'static>(value: &dyn Any) -> &T {
+    value
+        .downcast_ref()
+        .expect("native instance wraps its class's type")
+}
+
+fn downcast_mut<T: 'static>(value: &mut dyn Any) -> &mut T {
+    value
+        .downcast_mut()
+        .expect("native instance wraps its class's type")
+}
+
+//a rust value wrapped up for lox
+pub struct LoxNativeInstance {
+    pub class: Rc<LoxNativeClass>,
+    value: RefCell<Box<dyn Any>>,
+}
+
+impl LoxNativeInstance {
+    pub fn new<T: NativeClass>(class: Rc<LoxNativeClass>, value: T) -> Self {
+        Self {
+            class,
+            value: RefCell::new(Box::new(value)),
+        }
+    }
+
+    //the wrapped value, if it's a T and isn't already borrowed mutably, e.g. by a method
+    //running on this instance
+    pub fn borrow<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.value.try_borrow().ok()?, |value| value.downcast_ref()).ok()
+    }
+
+    pub fn borrow_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.value.try_borrow_mut().ok()?, |value| {
+            value.downcast_mut()
+        })
+        .ok()
+    }
+
+    //properties shadow methods. methods come back bound to the instance. Ok(None) if the
+    //class has neither by that name
+    pub fn get(self: &Rc<Self>, name: Symbol) -> Result<Option<Value>, String> {
+        if let Some(getter) = self.class.getters.get(&name) {
+            let value = self.value.try_borrow().map_err(|_| self.in_use())?;
+
+            return Ok(Some(getter(value.as_ref())));
+        }
+
+        let (arity, method) = match self.class.methods.get(&name) {
+            Some(method) => method,
+            None => return Ok(None),
+        };
+        let instance = Rc::clone(self);
+        let method = Rc::clone(method);
+
+        Ok(Some(Value::Native(Rc::new(NativeFunction::new(
+            name.as_str(),
+            *arity,
+            move |args| {
+                let mut value = instance
+                    .value
+                    .try_borrow_mut()
+                    .map_err(|_| instance.in_use())?;
+
+                method(value.as_mut(), args)
+            },
+        )))))
+    }
+
+    //Ok(false) if the class has no setter by that name
+    pub fn set(&self, name: Symbol, value: Value) -> Result<bool, String> {
+        let setter = match self.class.setters.get(&name) {
+            Some(setter) => setter,
+            None => return Ok(false),
+        };
+
+        let mut this = self.value.try_borrow_mut().map_err(|_| self.in_use())?;
+
+        setter(this.as_mut(), value).map(|_| true)
+    }
+
+    //natives can't call back into lox, so the value is only ever already borrowed when rust
+    //code is holding on to it, e.g. through borrow_mut, while lox runs
+    fn in_use(&self) -> String {
+        format!("{} instance is already in use.", self.class.name)
+    }
+}
+
+impl fmt::Debug for LoxNativeInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxNativeInstance({self})")
+    }
+}
+
+impl fmt::Display for LoxNativeInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lox::*;
+    use crate::output::*;
+
+    struct Account {
+        owner: String,
+        balance: f64,
+    }
+
+    impl NativeClass for Account {
+        const NAME: &'static str = "Account";
+
+        fn define(class: &mut NativeClassBuilder<Self>) {
+            class
+                .init(Arity::Fixed(1), |args| match &args[0] {
+                    Value::String(owner) => Ok(Account {
+                        owner: owner.to_string(),
+                        balance: 0.0,
+                    }),
+                    _ => Err(String::from("Owner must be a string.")),
+                })
+                .getter("balance", |account| Value::Number(account.balance))
+                .getter("owner", |account| Value::from(account.owner.as_str()))
+                .setter("owner", |account, value| match value {
+                    Value::String(owner) => {
+                        account.owner = owner.to_string();
+                        Ok(())
+                    }
+                    _ => Err(String::from("Owner must be a string.")),
+                })
+                .method("deposit", Arity::Fixed(1), |account, args| match args[0] {
+                    Value::Number(amount) => {
+                        account.balance += amount;
+                        Ok(Value::Number(account.balance))
+                    }
+                    _ => Err(String::from("Amount must be a number.")),
+                });
+        }
+    }
+
+    //only ever made on the rust side
+    struct Handle;
+
+    impl NativeClass for Handle {
+        const NAME: &'static str = "Handle";
+
+        fn define(_: &mut NativeClassBuilder<Self>) {}
+    }
+
+    fn lox() -> (Lox, SharedBuffer) {
+        let output = SharedBuffer::new();
+        let mut lox = Lox::new();
+
+        lox.set_output(output.clone());
+        lox.define_class::<Account>();
+        lox.define_class::<Handle>();
+
+        (lox, output)
+    }
+
+    fn runtime_error(lox: &mut Lox, source: &str) -> String {
+        match lox.eval(source) {
+            Err(LoxError::Runtime(err)) => err.message,
+            other => panic!("expected a runtime error from {source:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn used_from_lox() {
+        let (mut lox, output) = lox();
+
+        let result = lox.eval(
+            "var account = Account(\"ada\");
+             account.deposit(10);
+             var deposit = account.deposit;
+             deposit(5);
+             account.owner = \"grace\";
+             print account;
+             print account.owner;
+             account.balance",
+        );
+
+        assert_eq!(result.unwrap(), Value::Number(15.0));
+        assert_eq!(output.take(), "Account instance\ngrace\n");
+
+        //the rust value behind it has changed too
+        let Some(Value::NativeInstance(instance)) = lox.get_global("account") else {
+            panic!("account is a native instance");
+        };
+        let account = instance.borrow::<Account>().expect("it's an Account");
+
+        assert_eq!(account.owner, "grace");
+        assert_eq!(account.balance, 15.0);
+        assert!(instance.borrow::<Handle>().is_none());
+    }
+
+    #[test]
+    fn made_in_rust() {
+        let (mut lox, _) = lox();
+
+        let account = lox.native_instance(Account {
+            owner: String::from("ada"),
+            balance: 100.0,
+        });
+        lox.set_global("account", account);
+
+        assert_eq!(
+            lox.eval("account.deposit(1)").unwrap(),
+            Value::Number(101.0)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let (mut lox, _) = lox();
+
+        lox.eval("var account = Account(\"ada\");").unwrap();
+
+        assert_eq!(
+            runtime_error(&mut lox, "Account();"),
+            "Expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "Account(1);"),
+            "Owner must be a string."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "Handle();"),
+            "Can't create 'Handle' instances from lox."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "account.missing;"),
+            "Undefined property 'missing'."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "account.balance = 1;"),
+            "Can't set property 'balance' on Account instance."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "account.owner = 1;"),
+            "Owner must be a string."
+        );
+        assert_eq!(
+            runtime_error(&mut lox, "account.deposit(\"lots\");"),
+            "Amount must be a number."
+        );
+    }
+
+    //rust holding on to the value while lox runs is a runtime error in lox, not a panic
+    #[test]
+    fn already_borrowed() {
+        let (mut lox, _) = lox();
+
+        lox.eval("var account = Account(\"ada\");").unwrap();
+
+        let Some(Value::NativeInstance(instance)) = lox.get_global("account") else {
+            panic!("account is a native instance");
+        };
+        let borrowed = instance.borrow_mut::<Account>().expect("it's an Account");
+
+        for source in [
+            "account.balance;",
+            "account.owner = \"grace\";",
+            "account.deposit(1);",
+        ] {
+            assert_eq!(
+                runtime_error(&mut lox, source),
+                "Account instance is already in use."
+            );
+        }
+
+        drop(borrowed);
+
+        assert_eq!(lox.eval("account.balance").unwrap(), Value::Number(0.0));
+    }
+}
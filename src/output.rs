@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+//somewhere to write program output or diagnostics, e.g. stdout, a log file or a SharedBuffer
+pub struct Output(Box<dyn Write>);
+
+impl Output {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self(Box::new(writer))
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl From<Box<dyn Write>> for Output {
+    fn from(writer: Box<dyn Write>) -> Self {
+        Self(writer)
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Output")
+    }
+}
+
+//an in memory Write whose clones all share one buffer, so output handed to Lox as one clone
+//can be read back through another
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //everything written so far. invalid utf-8 is replaced rather than failing
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    //like contents, but empties the buffer too
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,152 +1,1346 @@
-use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
 
 use crate::expr::*;
+use crate::scanner::*;
+use crate::stmt::*;
+use crate::symbol::*;
 use crate::token::*;
 use crate::tokentype::*;
 
-#[derive(Clone)]
-pub struct Parser<'a> {
-    tokens: Vec<Token<'a>>,
-    current: usize,
-}
+type TokenStream<'a> = Box<dyn Iterator<Item = Result<Token, ScanError>> + 'a>;
+
+type PrefixFn<'a> = fn(&mut Parser<'a>, Token) -> Result<Expr>;
+type InfixFn<'a> = fn(&mut Parser<'a>, Expr, Token) -> Result<Expr>;
+
+//how tightly an infix operator binds, loosest first
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum Precedence {
+    Lowest,
+    Comma,
+    Assignment,
+    Ternary,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Term,
+    Factor,
+    Unary,
+    Exponent,
+    Call,
+}
+
+impl Precedence {
+    fn next(self) -> Self {
+        match self {
+            Precedence::Lowest => Precedence::Comma,
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Ternary,
+            Precedence::Ternary => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Call,
+            Precedence::Call => Precedence::Call,
+        }
+    }
+}
+
+//what kind of function body we're in, for the checks on `return`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FunctionKind {
+    Function,
+    Method,
+    //`init`, which can only return early without a value
+    Initializer,
+}
+
+impl FunctionKind {
+    //how the function is referred to in error messages
+    fn describe(self) -> &'static str {
+        match self {
+            FunctionKind::Function => "function",
+            FunctionKind::Method | FunctionKind::Initializer => "method",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ClassKind {
+    Class,
+    Subclass,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+struct ParseRule<'a> {
+    prefix: Option<PrefixFn<'a>>,
+    infix: Option<InfixFn<'a>>,
+    precedence: Precedence,
+    associativity: Associativity,
+}
+
+//tokens are pulled one at a time, so only the current and previous token are ever held
+pub struct Parser<'a> {
+    tokens: TokenStream<'a>,
+    current: Token,
+    previous: Option<Token>,
+    //tokens after current, only pulled when something needs to look that far
+    lookahead: VecDeque<Token>,
+    scan_errors: Vec<ScanError>,
+    //errors that don't stop the parse, like a `break` outside of a loop. they're found
+    //after the construct has been parsed fine, so there's nothing to recover from
+    errors: Vec<anyhow::Error>,
+    //things that are allowed but probably a mistake
+    warnings: Vec<ParseError>,
+    //the innermost function body we're inside, to catch a top level `return`
+    function: Option<FunctionKind>,
+    //classes we're inside, innermost last, to catch a stray `this` or `super`
+    classes: Vec<ClassKind>,
+    //labels of the loops we're inside, innermost last. unlabelled loops push None
+    loops: Vec<Option<Symbol>>,
+    //the local scopes we're inside, innermost last, with whether each name in them is ready
+    //to use or still being initialized. the top level isn't one of them, so a name that isn't
+    //found in any is a global
+    scopes: Vec<HashMap<Symbol, bool>>,
+    //span of the expression an infix parse function was handed as its left operand
+    left_span: Span,
+    //whether the last expression statement can leave off its `;`, for Lox::eval
+    trailing_expression: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self::from_stream(Box::new(tokens.into_iter().map(Ok)))
+    }
+
+    pub fn from_scanner(scanner: Scanner<'a>) -> Self {
+        Self::from_stream(Box::new(scanner))
+    }
+
+    fn from_stream(tokens: TokenStream<'a>) -> Self {
+        let mut parser = Self {
+            tokens,
+            current: Token::new(Box::new(TokenType::Eof), "", "", 1),
+            previous: None,
+            lookahead: VecDeque::new(),
+            scan_errors: vec![],
+            errors: vec![],
+            warnings: vec![],
+            function: None,
+            classes: vec![],
+            loops: vec![],
+            scopes: vec![],
+            left_span: Span::default(),
+            trailing_expression: false,
+        };
+
+        parser.current = parser.next_token();
+        parser
+    }
+
+    //lets the source end in an expression with no `;` after it, like `x + 1`
+    pub fn allow_trailing_expression(&mut self) {
+        self.trailing_expression = true;
+    }
+
+    //errors the scanner ran into while we were pulling tokens
+    pub fn scan_errors(&self) -> &[ScanError] {
+        &self.scan_errors
+    }
+
+    pub fn warnings(&self) -> &[ParseError] {
+        &self.warnings
+    }
+
+    //parses a whole program. after an error we skip ahead to the next statement and keep going,
+    //so every error gets reported rather than just the first
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<anyhow::Error>> {
+        let mut statements = vec![];
+
+        while !self.is_at_end() {
+            let declaration = if self.matching(&[TokenType::Export]) {
+                self.export_declaration()
+            } else {
+                self.declaration()
+            };
+
+            statements.extend(self.recover(declaration));
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    //`export` followed by a var, fun or class declaration
+    fn export_declaration(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+        let declaration = self.declaration()?;
+
+        if declaration.declared_name().is_none() {
+            return Err(self.error(&keyword, "Expect declaration after 'export'."));
+        }
+
+        Ok(Stmt::Export(Box::new(declaration)))
+    }
+
+    fn declaration(&mut self) -> Result<Stmt> {
+        if self.matching(&[TokenType::Export]) {
+            let keyword = self.previous()?.clone();
+            self.report(&keyword, "Can only export from the top level of a module.");
+        }
+
+        if self.matching(&[TokenType::Var]) {
+            return self.var_declaration();
+        }
+
+        if self.matching(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+
+        //`fun (` starts a lambda, which is left to the expression parser
+        if self.check(&TokenType::Fun) && *self.peek_nth(1).token_type == TokenType::Identifier {
+            self.advance()?;
+
+            //defined before the body, so the function can call itself
+            let name = self.current.clone();
+            self.declare(&name);
+            self.define(&name);
+
+            return Ok(Stmt::Function(self.function(FunctionKind::Function)?));
+        }
+
+        self.statement()
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect class name.")?
+            .clone();
+        self.declare(&name);
+        self.define(&name);
+
+        let superclass = if self.matching(&[TokenType::Less]) {
+            let superclass = self
+                .consume(&TokenType::Identifier, "Expect superclass name.")?
+                .clone();
+
+            if superclass.lexeme == name.lexeme {
+                self.report(&superclass, "A class can't inherit from itself.");
+            }
+
+            let depth = self.resolve(&superclass);
+            Some(Expr::Variable(superclass, depth))
+        } else {
+            None
+        };
+
+        self.consume(&TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        self.classes.push(match superclass {
+            Some(_) => ClassKind::Subclass,
+            None => ClassKind::Class,
+        });
+        //the methods close over a scope holding `super` in a subclass, and are bound to an
+        //instance in one holding `this`
+        let methods = match superclass {
+            Some(_) => self.scoped(|parser| parser.scoped(Self::methods)),
+            None => self.scoped(Self::methods),
+        };
+        self.classes.pop();
+
+        Ok(Stmt::Class(name, superclass, methods?))
+    }
+
+    fn methods(&mut self) -> Result<Vec<Arc<Function>>> {
+        let mut methods = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let kind = if self.current.lexeme.as_ref() == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+
+            methods.push(self.function(kind)?);
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(methods)
+    }
+
+    //the `fun` keyword, if there is one, has already been consumed
+    fn function(&mut self, kind: FunctionKind) -> Result<Arc<Function>> {
+        let name = self
+            .consume(
+                &TokenType::Identifier,
+                &format!("Expect {} name.", kind.describe()),
+            )?
+            .clone();
+
+        self.consume(
+            &TokenType::LeftParen,
+            &format!("Expect '(' after {} name.", kind.describe()),
+        )?;
+
+        self.function_rest(Some(name), kind)
+    }
+
+    //the parameter list and body. the opening paren has already been consumed
+    fn function_rest(&mut self, name: Option<Token>, kind: FunctionKind) -> Result<Arc<Function>> {
+        let mut params = vec![];
+
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(self.error(&self.current, "Can't have more than 255 parameters."));
+                }
+
+                let param = self
+                    .consume(&TokenType::Identifier, "Expect parameter name.")?
+                    .clone();
+                params.push(param);
+
+                if !self.matching(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(&TokenType::RightParen, "Expect ')' after parameters.")?;
+        self.consume(
+            &TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind.describe()),
+        )?;
+
+        let body = self.function_body(kind, &params, |parser| parser.block())?;
+
+        Ok(Arc::new(Function { name, params, body }))
+    }
+
+    //parses a function body with `return` allowed in it, in the same scope as the parameters.
+    //loops outside the function can't be broken out of from inside it
+    fn function_body<T>(
+        &mut self,
+        kind: FunctionKind,
+        params: &[Token],
+        parse: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let loops = std::mem::take(&mut self.loops);
+        let enclosing = self.function.replace(kind);
+        let body = self.scoped(|parser| {
+            for param in params {
+                parser.declare(param);
+                parser.define(param);
+            }
+
+            parse(parser)
+        });
+        self.function = enclosing;
+        self.loops = loops;
+
+        body
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect variable name.")?
+            .clone();
+        self.declare(&name);
+
+        let initializer = if self.matching(&[TokenType::Equal]) {
+            Some(self.expression())
+        } else {
+            None
+        };
+
+        //even if the initializer failed to parse, so the error isn't followed by one for
+        //every later use
+        self.define(&name);
+        let initializer = initializer.transpose()?;
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+
+        Ok(Stmt::Var(name, initializer))
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.matching(&[TokenType::Print]) {
+            return self.print_statement();
+        }
+
+        if self.matching(&[TokenType::LeftBrace]) {
+            return Ok(Stmt::Block(self.scoped(Self::block)?));
+        }
+
+        if self.matching(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+
+        if self.matching(&[TokenType::If]) {
+            return self.if_statement();
+        }
+
+        if self.matching(&[TokenType::While, TokenType::For]) {
+            return self.loop_statement(None);
+        }
+
+        if self.matching(&[TokenType::Break, TokenType::Continue]) {
+            return self.jump_statement();
+        }
+
+        if self.matching(&[TokenType::Throw]) {
+            return self.throw_statement();
+        }
+
+        if self.matching(&[TokenType::Try]) {
+            return self.try_statement();
+        }
+
+        if self.matching(&[TokenType::Match]) {
+            return self.match_statement();
+        }
+
+        if self.matching(&[TokenType::Import]) {
+            return self.import_statement();
+        }
+
+        //`name: while (...)` labels a loop
+        if self.check(&TokenType::Identifier) && *self.peek_nth(1).token_type == TokenType::Colon {
+            let label = self.advance()?.clone();
+            self.advance()?;
+
+            if !self.matching(&[TokenType::While, TokenType::For]) {
+                return Err(self.error(&self.current, "Expect loop after label."));
+            }
+
+            return self.loop_statement(Some(label));
+        }
+
+        self.expression_statement()
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.matching(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(condition, then_branch, else_branch))
+    }
+
+    //the `while` or `for` keyword has already been consumed
+    fn loop_statement(&mut self, label: Option<Token>) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+
+        self.loops
+            .push(label.as_ref().and_then(|label| label.symbol));
+        let statement = if *keyword.token_type == TokenType::While {
+            self.while_statement(label)
+        } else {
+            self.for_statement(label)
+        };
+        self.loops.pop();
+
+        statement
+    }
+
+    fn while_statement(&mut self, label: Option<Token>) -> Result<Stmt> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after condition.")?;
+
+        let body = self.statement()?;
+
+        Ok(Stmt::While(label, condition, Box::new(body), None))
+    }
+
+    //desugared into a while loop, wrapped in a block when there's an initializer
+    fn for_statement(&mut self, label: Option<Token>) -> Result<Stmt> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        //`x in` or `k, v in`
+        let is_for_in = self.check(&TokenType::Identifier)
+            && match *self.peek_nth(1).token_type {
+                TokenType::In => true,
+                TokenType::Comma => {
+                    *self.peek_nth(2).token_type == TokenType::Identifier
+                        && *self.peek_nth(3).token_type == TokenType::In
+                }
+                _ => false,
+            };
+
+        if is_for_in {
+            return self.for_in_statement(label);
+        }
+
+        if self.matching(&[TokenType::Semicolon]) {
+            return self.for_clauses(label, None);
+        }
+
+        //the block the initializer puts the loop in is a scope of its own
+        self.scoped(|parser| {
+            let initializer = if parser.matching(&[TokenType::Var]) {
+                parser.var_declaration()?
+            } else {
+                parser.expression_statement()?
+            };
+
+            parser.for_clauses(label, Some(initializer))
+        })
+    }
+
+    //the rest of a `for` after its initializer
+    fn for_clauses(&mut self, label: Option<Token>, initializer: Option<Stmt>) -> Result<Stmt> {
+        let condition = if self.check(&TokenType::Semicolon) {
+            Expr::Literal(Box::new(Literal::True))
+        } else {
+            self.expression()?
+        };
+        self.consume(&TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.statement()?;
+        let body = Stmt::While(label, condition, Box::new(body), increment);
+
+        Ok(match initializer {
+            Some(initializer) => Stmt::Block(vec![initializer, body]),
+            None => body,
+        })
+    }
+
+    //`for (x in iterable)` or `for (a, b in iterable)`, the `(` has already been consumed
+    fn for_in_statement(&mut self, label: Option<Token>) -> Result<Stmt> {
+        let mut names = vec![self.advance()?.clone()];
+
+        if self.matching(&[TokenType::Comma]) {
+            names.push(self.advance()?.clone());
+        }
+
+        let keyword = self.consume(&TokenType::In, "Expect 'in'.")?.clone();
+        let iterable = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after for-in clause.")?;
+
+        //each pass through the body gets a scope with the loop variables in it
+        let body = self.scoped(|parser| {
+            for name in &names {
+                parser.declare(name);
+                parser.define(name);
+            }
+
+            parser.statement()
+        })?;
+
+        Ok(Stmt::ForIn(label, names, iterable, Box::new(body), keyword))
+    }
+
+    //`break` or `continue`, optionally naming the loop they apply to
+    fn jump_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+
+        let label = if self.check(&TokenType::Identifier) {
+            Some(self.advance()?.clone())
+        } else {
+            None
+        };
+
+        self.consume(
+            &TokenType::Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+
+        if self.loops.is_empty() {
+            self.report(
+                &keyword,
+                &format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            );
+        } else if let Some(label) = &label {
+            if !self.loops.contains(&label.symbol) {
+                self.report(
+                    label,
+                    &format!("No enclosing loop labelled '{}'.", label.lexeme),
+                );
+            }
+        }
+
+        Ok(match *keyword.token_type {
+            TokenType::Break => Stmt::Break(keyword, label),
+            _ => Stmt::Continue(keyword, label),
+        })
+    }
+
+    fn import_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+        let path = self
+            .consume(&TokenType::String, "Expect module path after 'import'.")?
+            .clone();
+        self.consume(&TokenType::As, "Expect 'as' after module path.")?;
+        let name = self
+            .consume(&TokenType::Identifier, "Expect module name after 'as'.")?
+            .clone();
+        self.consume(&TokenType::Semicolon, "Expect ';' after import.")?;
+        self.declare(&name);
+        self.define(&name);
+
+        Ok(Stmt::Import(keyword, path, name))
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+        let value = self.expression()?;
+
+        self.consume(&TokenType::Semicolon, "Expect ';' after thrown value.")?;
+
+        Ok(Stmt::Throw(keyword, value))
+    }
+
+    //`try { } catch (e) { } finally { }`, where either clause can be left off but not both
+    fn try_statement(&mut self) -> Result<Stmt> {
+        self.consume(&TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let body = self.scoped(Self::block)?;
+
+        let catch = if self.matching(&[TokenType::Catch]) {
+            self.consume(&TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self
+                .consume(&TokenType::Identifier, "Expect error variable name.")?
+                .clone();
+            self.consume(&TokenType::RightParen, "Expect ')' after error variable.")?;
+            self.consume(&TokenType::LeftBrace, "Expect '{' before catch body.")?;
+
+            //the error variable is in the same scope as the catch body
+            let handler = self.scoped(|parser| {
+                parser.declare(&name);
+                parser.define(&name);
+                parser.block()
+            })?;
+
+            Some((name, handler))
+        } else {
+            None
+        };
+
+        let finally = if self.matching(&[TokenType::Finally]) {
+            self.consume(&TokenType::LeftBrace, "Expect '{' after 'finally'.")?;
+
+            Some(self.scoped(Self::block)?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(
+                &self.current,
+                "Expect 'catch' or 'finally' after try block.",
+            ));
+        }
+
+        Ok(Stmt::Try(body, catch, finally))
+    }
+
+    //`match (value) { pattern => statement ... }`. a statement already ends in a `;` or `}`,
+    //so arms need no separator, but a comma is allowed after a block
+    fn match_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let value = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after match value.")?;
+        self.consume(&TokenType::LeftBrace, "Expect '{' before match arms.")?;
+
+        let mut arms = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            self.consume(&TokenType::Arrow, "Expect '=>' after pattern.")?;
+
+            //what the pattern binds is in a scope around the arm. alternatives can bind the
+            //same name, so that isn't a redeclaration
+            let body = self.scoped(|parser| {
+                let mut names = vec![];
+                pattern.bindings(&mut names);
+
+                for name in names {
+                    parser.define(name);
+                }
+
+                parser.statement()
+            })?;
+
+            if matches!(body, Stmt::Block(_)) {
+                self.matching(&[TokenType::Comma]);
+            }
+
+            arms.push((pattern, body));
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after match arms.")?;
+
+        if !arms.iter().any(|(pattern, _)| pattern.is_catch_all()) {
+            self.warnings.push(ParseError::new(
+                &keyword,
+                "Match has no catch-all arm, so some values won't match any arm.",
+            ));
+        }
+
+        Ok(Stmt::Match(keyword, value, arms))
+    }
+
+    //whichever alternative matches, the arm sees the same names, so they all have to bind them
+    fn pattern(&mut self) -> Result<Pattern> {
+        let mut alternatives = vec![self.single_pattern()?];
+
+        while self.matching(&[TokenType::Pipe]) {
+            let pipe = self.previous()?.clone();
+            let alternative = self.single_pattern()?;
+
+            if Self::bound_names(&alternative) != Self::bound_names(&alternatives[0]) {
+                self.report(&pipe, "Alternatives must bind the same names.");
+            }
+
+            alternatives.push(alternative);
+        }
+
+        Ok(match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Pattern::Alternation(alternatives),
+        })
+    }
+
+    fn bound_names(pattern: &Pattern) -> BTreeSet<&str> {
+        let mut names = vec![];
+        pattern.bindings(&mut names);
+
+        names.into_iter().map(|name| &*name.lexeme).collect()
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern> {
+        let token = self.advance()?.clone();
+
+        match *token.token_type {
+            TokenType::Number
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil => Ok(Pattern::Literal(Literal::from(&token))),
+            TokenType::Minus => {
+                let number = self.consume(&TokenType::Number, "Expect number after '-'.")?;
+
+                match Literal::from(number) {
+                    Literal::Number(n) => Ok(Pattern::Literal(Literal::Number(-n))),
+                    _ => unreachable!("number tokens make number literals"),
+                }
+            }
+            TokenType::LeftBracket => {
+                let mut elements = vec![];
+
+                while !self.check(&TokenType::RightBracket) {
+                    elements.push(self.pattern()?);
+
+                    if !self.matching(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(&TokenType::RightBracket, "Expect ']' after list pattern.")?;
+
+                Ok(Pattern::List(elements))
+            }
+            TokenType::Identifier if self.matching(&[TokenType::LeftBrace]) => {
+                let mut fields = vec![];
+
+                while !self.check(&TokenType::RightBrace) {
+                    let field = self
+                        .consume(&TokenType::Identifier, "Expect field name.")?
+                        .clone();
+
+                    let pattern = if self.matching(&[TokenType::Colon]) {
+                        self.pattern()?
+                    } else {
+                        Pattern::Binding(field.clone())
+                    };
+
+                    fields.push((field, pattern));
+
+                    if !self.matching(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(&TokenType::RightBrace, "Expect '}' after field patterns.")?;
+
+                let depth = self.resolve(&token);
+                Ok(Pattern::Instance(token, depth, fields))
+            }
+            TokenType::Identifier if token.lexeme.as_ref() == "_" => Ok(Pattern::Wildcard),
+            TokenType::Identifier => Ok(Pattern::Binding(token)),
+            _ => Err(self.error(&token, "Expect pattern.")),
+        }
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+
+        if self.function.is_none() {
+            self.report(&keyword, "Can't return from top-level code.");
+        }
+
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(&TokenType::Semicolon, "Expect ';' after return value.")?;
+
+        if value.is_some() && self.function == Some(FunctionKind::Initializer) {
+            self.report(&keyword, "Can't return a value from an initializer.");
+        }
+
+        Ok(Stmt::Return(keyword, value))
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous()?.clone();
+        let value = self.expression()?;
+
+        self.consume(&TokenType::Semicolon, "Expect ';' after value.")?;
+
+        Ok(Stmt::Print(keyword, value))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt> {
+        let expr = self.expression()?;
+
+        if self.trailing_expression && self.is_at_end() {
+            return Ok(Stmt::Expression(expr));
+        }
+
+        self.consume(&TokenType::Semicolon, "Expect ';' after expression.")?;
+
+        Ok(Stmt::Expression(expr))
+    }
+
+    //the opening brace has already been consumed
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        let mut statements = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let declaration = self.declaration();
+            statements.extend(self.recover(declaration));
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after block.")?;
+
+        Ok(statements)
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.parse_precedence(Precedence::Lowest)
+    }
+
+    //the core of the pratt parser: parse a prefix expression, then keep folding infix
+    //operators into it for as long as they bind at least as tightly as `precedence`
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<Expr> {
+        if self.is_at_end() {
+            return Err(self.error(&self.current, "Expect expression."));
+        }
+
+        //a token that can't start an expression is left for synchronize() to skip, so it
+        //doesn't skip the token after it too
+        let prefix = match Self::rule(&self.current.token_type).prefix {
+            Some(prefix) => prefix,
+            None => return Err(self.error(&self.current, "Expect expression.")),
+        };
+
+        let token = self.advance()?.clone();
+        let start = token.span;
+
+        let mut expr = prefix(self, token)?;
+
+        loop {
+            let rule = Self::rule(&self.current.token_type);
+
+            let infix = match rule.infix {
+                Some(infix) if rule.precedence >= precedence => infix,
+                _ => break,
+            };
+
+            self.left_span = start.to(self.previous()?.span);
+
+            let operator = self.advance()?.clone();
+            expr = infix(self, expr, operator)?;
+        }
+
+        Ok(expr)
+    }
+
+    //the operator table. every token that can start or continue an expression is listed here,
+    //along with how tightly it binds when used as an infix operator
+    fn rule(token_type: &TokenType) -> ParseRule<'a> {
+        use Associativity::*;
+        use Precedence as P;
+
+        let (prefix, infix, precedence, associativity): (Option<PrefixFn>, Option<InfixFn>, _, _) =
+            match token_type {
+                TokenType::LeftParen => (Some(Self::grouping), Some(Self::call), P::Call, Left),
+                TokenType::Fun => (Some(Self::lambda), None, P::Lowest, Left),
+                TokenType::Dot => (None, Some(Self::dot), P::Call, Left),
+                TokenType::LeftBrace => (Some(Self::map), None, P::Lowest, Left),
+                TokenType::LeftBracket => (Some(Self::list), Some(Self::subscript), P::Call, Left),
+                TokenType::Equal => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::PlusEqual => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::MinusEqual => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::StarEqual => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::SlashEqual => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::PercentEqual => (None, Some(Self::assignment), P::Assignment, Right),
+                TokenType::PlusPlus => (
+                    Some(Self::prefix_increment),
+                    Some(Self::postfix_increment),
+                    P::Call,
+                    Left,
+                ),
+                TokenType::MinusMinus => (
+                    Some(Self::prefix_increment),
+                    Some(Self::postfix_increment),
+                    P::Call,
+                    Left,
+                ),
+                TokenType::Comma => (None, Some(Self::binary), P::Comma, Left),
+                TokenType::Question => (None, Some(Self::ternary), P::Ternary, Right),
+                TokenType::Or => (None, Some(Self::binary), P::Or, Left),
+                TokenType::And => (None, Some(Self::binary), P::And, Left),
+                TokenType::Minus => (Some(Self::unary), Some(Self::binary), P::Term, Left),
+                TokenType::Plus => (None, Some(Self::binary), P::Term, Left),
+                TokenType::Slash => (None, Some(Self::binary), P::Factor, Left),
+                TokenType::TildeSlash => (None, Some(Self::binary), P::Factor, Left),
+                TokenType::Star => (None, Some(Self::binary), P::Factor, Left),
+                TokenType::Percent => (None, Some(Self::binary), P::Factor, Left),
+                //binds tighter than unary minus on its left, so -2 ** 2 is -(2 ** 2)
+                TokenType::StarStar => (None, Some(Self::binary), P::Exponent, Right),
+                TokenType::Ampersand => (None, Some(Self::binary), P::BitAnd, Left),
+                TokenType::Pipe => (None, Some(Self::binary), P::BitOr, Left),
+                TokenType::Caret => (None, Some(Self::binary), P::BitXor, Left),
+                TokenType::LessLess => (None, Some(Self::binary), P::Shift, Left),
+                TokenType::GreaterGreater => (None, Some(Self::binary), P::Shift, Left),
+                TokenType::Tilde => (Some(Self::unary), None, P::Lowest, Left),
+                TokenType::Bang => (Some(Self::unary), None, P::Lowest, Left),
+                TokenType::BangEqual => (None, Some(Self::binary), P::Equality, Left),
+                TokenType::EqualEqual => (None, Some(Self::binary), P::Equality, Left),
+                TokenType::Greater => (None, Some(Self::binary), P::Comparison, Left),
+                TokenType::GreaterEqual => (None, Some(Self::binary), P::Comparison, Left),
+                TokenType::Less => (None, Some(Self::binary), P::Comparison, Left),
+                TokenType::LessEqual => (None, Some(Self::binary), P::Comparison, Left),
+                TokenType::Number => (Some(Self::literal), None, P::Lowest, Left),
+                TokenType::String => (Some(Self::literal), None, P::Lowest, Left),
+                TokenType::False => (Some(Self::literal), None, P::Lowest, Left),
+                TokenType::True => (Some(Self::literal), None, P::Lowest, Left),
+                TokenType::Nil => (Some(Self::literal), None, P::Lowest, Left),
+                TokenType::Identifier => (Some(Self::variable), None, P::Lowest, Left),
+                TokenType::This => (Some(Self::this), None, P::Lowest, Left),
+                TokenType::Super => (Some(Self::super_), None, P::Lowest, Left),
+                _ => (None, None, P::Lowest, Left),
+            };
+
+        ParseRule {
+            prefix,
+            infix,
+            precedence,
+            associativity,
+        }
+    }
+
+    fn binary(&mut self, left: Expr, operator: Token) -> Result<Expr> {
+        let rule = Self::rule(&operator.token_type);
+
+        //a left associative operator only takes operands that bind tighter than itself,
+        //so `a - b - c` stops after `b`. a right associative one takes its own level too
+        let right = match rule.associativity {
+            Associativity::Left => self.parse_precedence(rule.precedence.next())?,
+            Associativity::Right => self.parse_precedence(rule.precedence)?,
+        };
+
+        Ok(Expr::Binary(
+            Box::new(left),
+            self.operator(&operator)?,
+            Box::new(right),
+            operator,
+        ))
+    }
+
+    //the middle of `a ? b : c` is delimited by the `?` and `:`, so it can be any expression,
+    //commas included. the else branch is parsed right associatively
+    fn ternary(&mut self, condition: Expr, _question: Token) -> Result<Expr> {
+        let then_branch = self.expression()?;
+
+        self.consume(
+            &TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.",
+        )?;
+
+        let else_branch = self.parse_precedence(Precedence::Ternary)?;
+
+        Ok(Expr::Ternary(
+            Box::new(condition),
+            Box::new(then_branch),
+            Box::new(else_branch),
+        ))
+    }
+
+    fn unary(&mut self, operator: Token) -> Result<Expr> {
+        let right = self.parse_precedence(Precedence::Unary)?;
+
+        Ok(Expr::Unary(
+            self.operator(&operator)?,
+            Box::new(right),
+            operator,
+        ))
+    }
+
+    //`=` and the compound `op=` forms. the target has already been parsed as an ordinary
+    //expression, so this is where we check it's something that can be assigned to
+    fn assignment(&mut self, target: Expr, operator: Token) -> Result<Expr> {
+        let target_span = self.left_span;
+        let value = Box::new(self.parse_precedence(Precedence::Assignment)?);
+
+        if *operator.token_type == TokenType::Equal {
+            return match target {
+                Expr::Variable(name, depth) => Ok(Expr::Assign(name, depth, value)),
+                Expr::Get(object, name) => Ok(Expr::Set(object, name, value)),
+                Expr::Index(object, index, bracket) => {
+                    Ok(Expr::SetIndex(object, index, value, bracket))
+                }
+                _ => Err(self.invalid_target(&operator, target_span)),
+            };
+        }
+
+        let target = self.assignment_target(target, target_span, &operator)?;
+
+        Ok(Expr::CompoundAssign(
+            Box::new(target),
+            self.operator(&operator)?,
+            value,
+            operator,
+        ))
+    }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: Vec<Token<'a>>) -> Self {
-        Self { tokens, current: 0 }
+    fn prefix_increment(&mut self, operator: Token) -> Result<Expr> {
+        let start = self.current.span;
+        let target = self.parse_precedence(Precedence::Unary)?;
+        let target_span = start.to(self.previous()?.span);
+
+        let target = self.assignment_target(target, target_span, &operator)?;
+
+        Ok(Expr::Increment(
+            Box::new(target),
+            self.operator(&operator)?,
+            Fixity::Prefix,
+            operator,
+        ))
     }
 
-    pub fn parse(&mut self) -> Result<Expr> {
-        self.expression()
+    fn postfix_increment(&mut self, target: Expr, operator: Token) -> Result<Expr> {
+        let target = self.assignment_target(target, self.left_span, &operator)?;
+
+        Ok(Expr::Increment(
+            Box::new(target),
+            self.operator(&operator)?,
+            Fixity::Postfix,
+            operator,
+        ))
     }
 
-    fn expression(&mut self) -> Result<Expr> {
-        self.equality()
+    fn assignment_target(&self, target: Expr, span: Span, operator: &Token) -> Result<Expr> {
+        match target {
+            Expr::Variable(_, _) | Expr::Get(_, _) | Expr::Index(_, _, _) => Ok(target),
+            _ => Err(self.invalid_target(operator, span)),
+        }
+    }
+
+    fn invalid_target(&self, operator: &Token, span: Span) -> anyhow::Error {
+        let mut error = ParseError::new(operator, "Invalid assignment target.");
+        error.span = span;
+
+        anyhow::Error::new(error)
     }
 
-    fn equality(&mut self) -> Result<Expr> {
-        let mut expr = self.clone().comparison()?;
+    fn dot(&mut self, object: Expr, _dot: Token) -> Result<Expr> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect property name after '.'.")?
+            .clone();
 
-        if self.matching(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-            let right = self.comparison()?;
-            let operator = self.previous()?;
+        Ok(Expr::Get(Box::new(object), name))
+    }
+
+    //`[a, b, c]`, a trailing comma is allowed
+    fn list(&mut self, _bracket: Token) -> Result<Expr> {
+        let mut elements = vec![];
 
-            expr = Expr::Binary(Box::new(expr), Operator::from(operator), Box::new(right));
+        while !self.check(&TokenType::RightBracket) {
+            //parsed above the comma operator, like call arguments
+            elements.push(self.parse_precedence(Precedence::Assignment)?);
 
-            return Ok(expr);
+            if !self.matching(&[TokenType::Comma]) {
+                break;
+            }
         }
 
-        Ok(expr)
+        self.consume(&TokenType::RightBracket, "Expect ']' after list elements.")?;
+
+        Ok(Expr::List(elements))
     }
 
-    fn comparison(&mut self) -> Result<Expr> {
-        let expr = self.term()?;
+    //`{key: value, ...}`. a `{` at the start of a statement is always a block, so a map literal
+    //can only show up where an expression is expected
+    fn map(&mut self, brace: Token) -> Result<Expr> {
+        let mut entries = vec![];
 
-        if self.matching(vec![
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let right = self.term()?;
-            let operator = self.previous()?;
+        while !self.check(&TokenType::RightBrace) {
+            let key = self.parse_precedence(Precedence::Assignment)?;
+            self.consume(&TokenType::Colon, "Expect ':' after map key.")?;
+            let value = self.parse_precedence(Precedence::Assignment)?;
 
-            let new_expr = Expr::Binary(Box::new(expr), Operator::from(operator), Box::new(right));
+            entries.push((key, value));
 
-            return Ok(new_expr);
+            if !self.matching(&[TokenType::Comma]) {
+                break;
+            }
         }
 
-        Ok(expr)
+        self.consume(&TokenType::RightBrace, "Expect '}' after map entries.")?;
+
+        Ok(Expr::Map(entries, brace))
     }
 
-    fn term(&mut self) -> Result<Expr> {
-        let mut expr = self.factor()?;
+    //`object[index]` or a slice, `object[start:end]`
+    fn subscript(&mut self, object: Expr, bracket: Token) -> Result<Expr> {
+        let start = if self.check(&TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+
+        let is_slice = self.matching(&[TokenType::Colon]);
+        let end = if is_slice && !self.check(&TokenType::RightBracket) {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+
+        let close = self.consume(&TokenType::RightBracket, "Expect ']' after index.")?;
 
-        if self.matching(vec![TokenType::Minus, TokenType::Plus]) {
-            let right = self.factor()?;
-            let operator = self.previous()?;
+        //runtime errors point at the whole subscript rather than just the bracket
+        let mut bracket = bracket;
+        bracket.span = bracket.span.to(close.span);
 
-            expr = Expr::Binary(Box::new(expr), Operator::from(operator), Box::new(right));
+        match (is_slice, start) {
+            (false, Some(index)) => Ok(Expr::Index(Box::new(object), index, bracket)),
+            (_, start) => Ok(Expr::Slice(Box::new(object), start, end, bracket)),
+        }
+    }
 
-            return Ok(expr);
+    fn variable(&mut self, name: Token) -> Result<Expr> {
+        //a local still being initialized can be assigned to, just not read
+        let symbol = Self::symbol(&name);
+        let initializing = self
+            .scopes
+            .last()
+            .is_some_and(|scope| scope.get(&symbol) == Some(&false));
+
+        if initializing && !self.check(&TokenType::Equal) {
+            self.report(&name, "Can't read local variable in its own initializer.");
         }
 
-        Ok(expr)
+        let depth = self.resolve(&name);
+        Ok(Expr::Variable(name, depth))
     }
 
-    fn factor(&mut self) -> Result<Expr> {
-        let mut expr = self.clone().unary()?;
+    fn call(&mut self, callee: Expr, _paren: Token) -> Result<Expr> {
+        let mut arguments = vec![];
 
-        if self.matching(vec![TokenType::Slash, TokenType::Star]) {
-            let right = self.unary()?;
-            let operator = self.previous()?;
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(self.error(&self.current, "Can't have more than 255 arguments."));
+                }
 
-            expr = Expr::Binary(Box::new(expr), Operator::from(operator), Box::new(right));
+                //parsed above the comma operator, otherwise `f(a, b)` would be one argument
+                arguments.push(self.parse_precedence(Precedence::Assignment)?);
 
-            return Ok(expr);
+                if !self.matching(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
 
-        Ok(expr)
+        let paren = self
+            .consume(&TokenType::RightParen, "Expect ')' after arguments.")?
+            .clone();
+
+        Ok(Expr::Call(Box::new(callee), paren, arguments))
+    }
+
+    fn this(&mut self, keyword: Token) -> Result<Expr> {
+        if self.classes.is_empty() {
+            self.report(&keyword, "Can't use 'this' outside of a class.");
+        }
+
+        Ok(Expr::This(keyword))
+    }
+
+    //`super.method`, which can only be used to look a method up, not on its own
+    fn super_(&mut self, keyword: Token) -> Result<Expr> {
+        self.consume(&TokenType::Dot, "Expect '.' after 'super'.")?;
+        let method = self
+            .consume(&TokenType::Identifier, "Expect superclass method name.")?
+            .clone();
+
+        match self.classes.last() {
+            None => self.report(&keyword, "Can't use 'super' outside of a class."),
+            Some(ClassKind::Class) => {
+                self.report(&keyword, "Can't use 'super' in a class with no superclass.")
+            }
+            Some(ClassKind::Subclass) => {}
+        }
+
+        Ok(Expr::Super(keyword, method))
+    }
+
+    //`fun (params) { body }`
+    fn lambda(&mut self, _fun: Token) -> Result<Expr> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+
+        Ok(Expr::Lambda(
+            self.function_rest(None, FunctionKind::Function)?,
+        ))
     }
 
-    fn unary(&mut self) -> Result<Expr> {
-        if self.matching(vec![TokenType::Bang, TokenType::Minus]) {
-            let right = self.unary()?;
-            let operator = self.previous()?;
+    //a parenthesized expression, or the parameter list of an arrow function. names are resolved
+    //as they're parsed, so which it is has to be known up front, by looking ahead for the `=>`
+    fn grouping(&mut self, _paren: Token) -> Result<Expr> {
+        if self.matching(&[TokenType::RightParen]) {
+            let arrow = self
+                .consume(&TokenType::Arrow, "Expect '=>' after '()'.")?
+                .clone();
+
+            return self.arrow_function(vec![], arrow);
+        }
+
+        if let Some(count) = self.arrow_params_ahead() {
+            let mut params = vec![];
+
+            for _ in 0..count {
+                params.push(self.advance()?.clone());
+                self.advance()?;
+            }
+
+            let arrow = self.advance()?.clone();
+            return self.arrow_function(params, arrow);
+        }
+
+        let expr = self.expression()?;
 
-            let expr = Expr::Unary(Operator::from(operator), Box::new(right));
+        self.consume(&TokenType::RightParen, "Expect ')' after expression.")?;
 
-            return Ok(expr);
+        if self.matching(&[TokenType::Arrow]) {
+            let arrow = self.previous()?.clone();
+            return Err(self.error(&arrow, "Arrow function parameters must be names."));
         }
 
-        self.primary()
+        Ok(Expr::Grouping(Box::new(expr)))
     }
 
-    fn primary(&mut self) -> Result<Expr> {
-        if self.matching(vec![TokenType::False]) {
-            return Ok(Expr::Literal(Box::new(Literal::False)));
-        } else if self.matching(vec![TokenType::True]) {
-            return Ok(Expr::Literal(Box::new(Literal::True)));
-        } else if self.matching(vec![TokenType::Nil]) {
-            return Ok(Expr::Literal(Box::new(Literal::Nil)));
-        } else if self.matching(vec![TokenType::Number, TokenType::String]) {
-            let lit = self.previous()?.literal;
+    //how many parameters there are if the tokens ahead are `a, b) =>`
+    fn arrow_params_ahead(&mut self) -> Option<usize> {
+        let mut count = 0;
 
-            return Ok(Expr::Literal(Box::new(Literal::from(lit))));
-        } else if self.matching(vec![TokenType::LeftParen]) {
-            let expr = self.expression()?;
+        loop {
+            if *self.peek_nth(2 * count).token_type != TokenType::Identifier {
+                return None;
+            }
 
-            self.consume(&TokenType::RightParen, "Expected ')' after expression");
+            count += 1;
 
-            return Ok(Expr::Grouping(Box::new(expr)));
-        } else {
-            return Err(anyhow!("Error from primary(): Expected expression"));
+            match *self.peek_nth(2 * count - 1).token_type {
+                TokenType::Comma => {}
+                TokenType::RightParen => break,
+                _ => return None,
+            }
         }
+
+        (*self.peek_nth(2 * count).token_type == TokenType::Arrow).then_some(count)
     }
 
-    //TODO: just iterate over token_types and use Rust iter API instead of this fn?
-    //this fn couples state and iteration, so i need &mut self, and many of my fns
-    //above require multiple mutable references to self
-    //so I can use some kind of poitner/reference API in those fns, or (at least for matching()),
-    //I can separate statefullness from iteration
+    //`(params) => expression` is short for a lambda that returns the expression
+    fn arrow_function(&mut self, params: Vec<Token>, arrow: Token) -> Result<Expr> {
+        let body = self.function_body(FunctionKind::Function, &params, |parser| {
+            parser.parse_precedence(Precedence::Assignment)
+        })?;
 
-    fn matching(&mut self, token_types: Vec<TokenType>) -> bool {
-        for token_type in &token_types {
+        Ok(Expr::Lambda(Arc::new(Function {
+            name: None,
+            params,
+            body: vec![Stmt::Return(arrow, Some(body))],
+        })))
+    }
+
+    fn literal(&mut self, token: Token) -> Result<Expr> {
+        Ok(Expr::Literal(Box::new(Literal::from(&token))))
+    }
+
+    fn matching(&mut self, token_types: &[TokenType]) -> bool {
+        for token_type in token_types {
             if self.check(token_type) {
-                self.advance();
-                return true;
+                return self.advance().is_ok();
             }
         }
 
         false
     }
 
-    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<&Token<'a>> {
+    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<&Token> {
         if self.check(token_type) {
-            return self.advance();
+            self.advance()
         } else {
-            // let peeked = self.peek()?;
-
-            return Err(anyhow!("{message}"));
+            Err(self.error(&self.current, message))
         }
     }
 
@@ -161,14 +1355,34 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn advance(&mut self) -> Result<&Token<'a>> {
+    fn advance(&mut self) -> Result<&Token> {
         if !self.is_at_end() {
-            self.current += 1;
+            let next = self.next_token();
+            self.previous = Some(std::mem::replace(&mut self.current, next));
         }
 
         self.previous()
     }
 
+    fn next_token(&mut self) -> Token {
+        match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.pull_token(),
+        }
+    }
+
+    //bad tokens are recorded and skipped so parsing can carry on.
+    //a stream that ends without an Eof token gets one made up for it
+    fn pull_token(&mut self) -> Token {
+        loop {
+            match self.tokens.next() {
+                Some(Ok(token)) => return token,
+                Some(Err(error)) => self.scan_errors.push(error),
+                None => return Token::new(Box::new(TokenType::Eof), "", "", self.current.line),
+            }
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         match self.peek() {
             Ok(token) => **token.token_type == TokenType::Eof,
@@ -176,58 +1390,901 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn peek(&self) -> Result<&Token<'a>> {
-        self.tokens
-            .get(self.current)
-            .context("Error in call to peek()")
+    fn peek(&self) -> Result<&Token> {
+        Ok(&self.current)
+    }
+
+    //the token `n` places after current, so peek_nth(0) is current
+    fn peek_nth(&mut self, n: usize) -> &Token {
+        if n == 0 {
+            return &self.current;
+        }
+
+        while self.lookahead.len() < n {
+            //the stream keeps handing out Eof once it's done, so this can't loop forever
+            let token = self.pull_token();
+            self.lookahead.push_back(token);
+        }
+
+        &self.lookahead[n - 1]
     }
 
-    fn previous(&self) -> Result<&Token<'a>> {
-        self.tokens
-            .get(self.current - 1)
+    fn previous(&self) -> Result<&Token> {
+        self.previous
+            .as_ref()
             .context("Error in call to previous()")
     }
 
-    // fn error(&self, token: Token, message: &str) -> ParseError {
-    //     //TODO: I just picked a random line number.
-    //     let lox = Lox::new();
-    //     lox.error(&token, message);
-
-    //     ParseError::new()
-    // }
-
-    //TODO: coming back to this fn later, it's not absolutely necessary.
-    //it synchronizes error output
-    // fn synchronize(&self) {
-    //     let token = self.advance()?;
-
-    //     while !self.is_at_end() {
-    //         // match self.previous() {
-    //         //     Some(token) => {
-    //         //         if *token.token_type == TokenType::Semicolon {
-    //         //             return;
-    //         //         }
-    //         //     }
-    //         //     None => (),
-    //         // }
-    //         if *self.previous()?.token_type == TokenType::Semicolon {
-    //             return;
-    //         }
-
-    //         // match self.peek() {
-    //         //     Some(token) => match *token.token_type {
-    //         //         TokenType::Return => return,
-    //         //         _ => (),
-    //         //     },
-    //         //     None => eprint!("Error calling self.peek() in synchronize()"),
-    //         // }
-    //         if *self.peek()?.token_type == TokenType::Return {
-    //             return;
-    //         }
-
-    //         self.advance();
-    //     }
-    // }
+    //the rule table only hands these operator tokens, so this can only fail on a bug in it
+    fn operator(&self, token: &Token) -> Result<Operator> {
+        Operator::try_from(token).map_err(|message| self.error(token, &message))
+    }
+
+    fn error(&self, token: &Token, message: &str) -> anyhow::Error {
+        anyhow::Error::new(ParseError::new(token, message))
+    }
+
+    //records an error and carries on parsing as if nothing was wrong
+    fn report(&mut self, token: &Token, message: &str) {
+        let error = self.error(token, message);
+        self.errors.push(error);
+    }
+
+    //parses something with a new local scope around it
+    fn scoped<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.scopes.push(HashMap::new());
+        let result = parse(self);
+        self.scopes.pop();
+
+        result
+    }
+
+    //adds a name to the innermost scope, but not ready to use until it's defined. a global can
+    //be declared again, a local can't
+    fn declare(&mut self, name: &Token) {
+        let symbol = Self::symbol(name);
+
+        let redeclared = self
+            .scopes
+            .last_mut()
+            .is_some_and(|scope| scope.insert(symbol, false).is_some());
+
+        if redeclared {
+            self.report(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        let symbol = Self::symbol(name);
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(symbol, true);
+        }
+    }
+
+    //how many scopes out a name is declared, from here. names not declared in any local scope
+    //are globals, which are as far out as the top level
+    fn resolve(&self, name: &Token) -> Depth {
+        let symbol = Self::symbol(name);
+
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&symbol))
+        {
+            Some(depth) => Depth::Local(depth),
+            None => Depth::Global(self.scopes.len()),
+        }
+    }
+
+    fn symbol(name: &Token) -> Symbol {
+        name.symbol.unwrap_or_else(|| Symbol::intern(&name.lexeme))
+    }
+
+    //keeps a declaration that parsed. otherwise the error is recorded and we skip to the next
+    //statement, at the top level or inside a block alike, like jlox's declaration()
+    fn recover(&mut self, declaration: Result<Stmt>) -> Option<Stmt> {
+        match declaration {
+            Ok(statement) => Some(statement),
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    //skips to what is probably the start of the next statement, so one mistake
+    //doesn't cascade into a pile of bogus errors
+    fn synchronize(&mut self) {
+        let _ = self.advance();
+
+        while !self.is_at_end() {
+            if let Ok(token) = self.previous() {
+                if *token.token_type == TokenType::Semicolon {
+                    return;
+                }
+            }
+
+            match *self.current.token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Match
+                | TokenType::Import
+                | TokenType::Export => return,
+                _ => {
+                    let _ = self.advance();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: u32,
+    //" at 'token'", " at end" or nothing
+    pub location: String,
+    pub message: String,
+    //the offending token, or the whole expression when that's more useful
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        let location = if *token.token_type == TokenType::Eof {
+            String::from(" at end")
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+
+        Self {
+            line: token.line,
+            location,
+            message: message.to_string(),
+            span: token.span,
+        }
+    }
 }
 
-pub struct ParseError;
\ No newline at end of file
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Error{}: {}",
+            self.line, self.location, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<Stmt>, Vec<String>> {
+        let mut parser = Parser::from_scanner(Scanner::new(source));
+
+        parser
+            .parse()
+            .map_err(|errors| errors.iter().map(|err| err.to_string()).collect())
+    }
+
+    //the expression statement source parses to, printed as an s-expression so the shape of
+    //the tree is easy to read, e.g. `1 + 2 * 3` is `(+ 1 (* 2 3))`
+    fn expr(source: &str) -> String {
+        let statements = parse(&format!("{source};"))
+            .unwrap_or_else(|errors| panic!("{source:?} didn't parse: {errors:?}"));
+
+        match statements.as_slice() {
+            [Stmt::Expression(expr)] => print(expr),
+            _ => panic!("{source:?} isn't a single expression statement"),
+        }
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        match parse(source) {
+            Ok(statements) => panic!("{source:?} parsed without errors: {statements:?}"),
+            Err(errors) => errors,
+        }
+    }
+
+    fn print(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(left, _, right, operator) => {
+                format!("({} {} {})", operator.lexeme, print(left), print(right))
+            }
+            Expr::Unary(_, right, operator) => format!("({} {})", operator.lexeme, print(right)),
+            Expr::Literal(literal) => match literal.as_ref() {
+                Literal::Number(number) => number.to_string(),
+                Literal::String(string) => format!("\"{string}\""),
+                Literal::True => String::from("true"),
+                Literal::False => String::from("false"),
+                Literal::Nil => String::from("nil"),
+                Literal::Expr(expr) => print(expr),
+            },
+            Expr::Grouping(expr) => format!("(group {})", print(expr)),
+            Expr::Ternary(condition, then_branch, else_branch) => format!(
+                "(? {} {} {})",
+                print(condition),
+                print(then_branch),
+                print(else_branch)
+            ),
+            Expr::Variable(name, _) => name.lexeme.to_string(),
+            Expr::Assign(name, _, value) => format!("(= {} {})", name.lexeme, print(value)),
+            Expr::Get(object, name) => format!("(. {} {})", print(object), name.lexeme),
+            Expr::Set(object, name, value) => {
+                format!("(= (. {} {}) {})", print(object), name.lexeme, print(value))
+            }
+            Expr::CompoundAssign(target, _, value, operator) => {
+                format!("({} {} {})", operator.lexeme, print(target), print(value))
+            }
+            Expr::Increment(target, _, fixity, operator) => match fixity {
+                Fixity::Prefix => format!("({} {})", operator.lexeme, print(target)),
+                Fixity::Postfix => format!("({} {})", print(target), operator.lexeme),
+            },
+            Expr::Call(callee, _, arguments) => format!("(call {})", list(callee, arguments)),
+            Expr::This(_) => String::from("this"),
+            Expr::Super(_, method) => format!("(super {})", method.lexeme),
+            Expr::Lambda(function) => {
+                let params: Vec<&str> = function.params.iter().map(|p| &*p.lexeme).collect();
+                format!("(fun ({}))", params.join(" "))
+            }
+            Expr::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(print).collect();
+                format!("[{}]", elements.join(" "))
+            }
+            Expr::Map(entries, _) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", print(key), print(value)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Expr::Index(object, index, _) => format!("(index {} {})", print(object), print(index)),
+            Expr::SetIndex(object, index, value, _) => format!(
+                "(= (index {} {}) {})",
+                print(object),
+                print(index),
+                print(value)
+            ),
+            Expr::Slice(object, start, end, _) => {
+                let bound =
+                    |bound: &Option<Box<Expr>>| bound.as_deref().map_or(String::from("_"), print);
+                format!("(slice {} {} {})", print(object), bound(start), bound(end))
+            }
+        }
+    }
+
+    fn list(first: &Expr, rest: &[Expr]) -> String {
+        let mut items = vec![print(first)];
+        items.extend(rest.iter().map(print));
+        items.join(" ")
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(expr("123"), "123");
+        assert_eq!(expr("1.5"), "1.5");
+        assert_eq!(expr("0xff"), "255");
+        assert_eq!(expr("\"str\""), "\"str\"");
+        assert_eq!(expr("true"), "true");
+        assert_eq!(expr("false"), "false");
+        assert_eq!(expr("nil"), "nil");
+        assert_eq!(expr("name"), "name");
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(expr("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(expr("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(expr("1 + 2 < 3 + 4"), "(< (+ 1 2) (+ 3 4))");
+        assert_eq!(expr("1 < 2 == 3 > 4"), "(== (< 1 2) (> 3 4))");
+        assert_eq!(expr("a == b and c != d"), "(and (== a b) (!= c d))");
+        assert_eq!(expr("a or b and c"), "(or a (and b c))");
+        assert_eq!(expr("a and b or c"), "(or (and a b) c)");
+        assert_eq!(expr("-a * b"), "(* (- a) b)");
+        assert_eq!(expr("!a == b"), "(== (! a) b)");
+        assert_eq!(expr("a % b + c ~/ d"), "(+ (% a b) (~/ c d))");
+        assert_eq!(expr("a = b or c"), "(= a (or b c))");
+        assert_eq!(expr("a ? b : c or d"), "(? a b (or c d))");
+        assert_eq!(expr("a = b ? c : d"), "(= a (? b c d))");
+    }
+
+    #[test]
+    fn bitwise_precedence() {
+        assert_eq!(expr("a | b ^ c & d"), "(| a (^ b (& c d)))");
+        assert_eq!(expr("a & b << c"), "(& a (<< b c))");
+        assert_eq!(expr("a << b + c"), "(<< a (+ b c))");
+        assert_eq!(expr("a == b | c"), "(== a (| b c))");
+        assert_eq!(expr("~a & b"), "(& (~ a) b)");
+    }
+
+    #[test]
+    fn exponent_binds_tighter_than_unary_minus() {
+        assert_eq!(expr("-2 ** 2"), "(- (** 2 2))");
+        assert_eq!(expr("2 ** -1"), "(** 2 (- 1))");
+        assert_eq!(expr("2 * 3 ** 2"), "(* 2 (** 3 2))");
+    }
+
+    #[test]
+    fn postfix_binds_tightest() {
+        assert_eq!(expr("-a.b"), "(- (. a b))");
+        assert_eq!(expr("-f(x)"), "(- (call f x))");
+        assert_eq!(expr("!xs[0]"), "(! (index xs 0))");
+        assert_eq!(expr("a.b.c(d)[e]"), "(index (call (. (. a b) c) d) e)");
+        assert_eq!(expr("-i++"), "(- (i ++))");
+        assert_eq!(expr("++a.b"), "(++ (. a b))");
+    }
+
+    #[test]
+    fn left_associativity() {
+        assert_eq!(expr("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(expr("1 / 2 / 3"), "(/ (/ 1 2) 3)");
+        assert_eq!(expr("a < b < c"), "(< (< a b) c)");
+        assert_eq!(expr("a == b == c"), "(== (== a b) c)");
+        assert_eq!(expr("a or b or c"), "(or (or a b) c)");
+        assert_eq!(expr("a << b >> c"), "(>> (<< a b) c)");
+        assert_eq!(expr("a, b, c"), "(, (, a b) c)");
+    }
+
+    #[test]
+    fn right_associativity() {
+        assert_eq!(expr("a = b = c"), "(= a (= b c))");
+        assert_eq!(expr("a += b -= c"), "(+= a (-= b c))");
+        assert_eq!(expr("2 ** 3 ** 2"), "(** 2 (** 3 2))");
+        assert_eq!(expr("a ? b : c ? d : e"), "(? a b (? c d e))");
+        assert_eq!(expr("--a"), "(-- a)");
+        assert_eq!(expr("- -a"), "(- (- a))");
+        assert_eq!(expr("!!a"), "(! (! a))");
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(expr("(1 + 2) * 3"), "(* (group (+ 1 2)) 3)");
+        assert_eq!(expr("1 - (2 - 3)"), "(- 1 (group (- 2 3)))");
+        assert_eq!(expr("((a))"), "(group (group a))");
+        assert_eq!(expr("-(2 ** 2)"), "(- (group (** 2 2)))");
+        assert_eq!(expr("(a, b)"), "(group (, a b))");
+    }
+
+    #[test]
+    fn comma_operator_is_loosest() {
+        assert_eq!(expr("a = 1, b = 2"), "(, (= a 1) (= b 2))");
+        //arguments, list elements and the middle of a ternary each stop at their own commas
+        assert_eq!(expr("f(a, b = c)"), "(call f a (= b c))");
+        assert_eq!(expr("[a, b]"), "[a b]");
+        assert_eq!(expr("a ? b, c : d"), "(? a (, b c) d)");
+    }
+
+    #[test]
+    fn assignment_targets() {
+        assert_eq!(expr("a.b = c"), "(= (. a b) c)");
+        assert_eq!(expr("a[0] = c"), "(= (index a 0) c)");
+        assert_eq!(expr("a.b *= 2"), "(*= (. a b) 2)");
+        assert_eq!(expr("xs[i]--"), "((index xs i) --)");
+    }
+
+    #[test]
+    fn calls_and_collections() {
+        assert_eq!(expr("f()"), "(call f)");
+        assert_eq!(expr("f(1)(2)"), "(call (call f 1) 2)");
+        assert_eq!(expr("[]"), "[]");
+        assert_eq!(expr("[1, [2],]"), "[1 [2]]");
+        assert_eq!(
+            expr("x = {\"a\": 1, b: 2 + 3}"),
+            "(= x {\"a\": 1, b: (+ 2 3)})"
+        );
+        assert_eq!(expr("xs[1:]"), "(slice xs 1 _)");
+        assert_eq!(expr("xs[:n - 1]"), "(slice xs _ (- n 1))");
+        assert_eq!(expr("fun (a, b) { return a; }"), "(fun (a b))");
+        assert_eq!(expr("(a, b) => a + b"), "(fun (a b))");
+        assert_eq!(expr("() => 1"), "(fun ())");
+    }
+
+    #[test]
+    fn statements() {
+        let statements = parse("var a = 1; print a; { a = 2; } if (a) print a; else a;").unwrap();
+
+        assert!(matches!(
+            statements.as_slice(),
+            [
+                Stmt::Var(_, Some(_)),
+                Stmt::Print(_, _),
+                Stmt::Block(_),
+                Stmt::If(_, _, Some(_)),
+            ]
+        ));
+
+        let statements = parse("for (var i = 0; i < 3; i = i + 1) print i;").unwrap();
+
+        //a for loop is desugared to a while loop inside a block holding the initializer
+        match statements.as_slice() {
+            [Stmt::Block(body)] => assert!(matches!(
+                body.as_slice(),
+                [Stmt::Var(_, _), Stmt::While(None, _, _, Some(_))]
+            )),
+            _ => panic!("for loop parsed to {statements:?}"),
+        }
+    }
+
+    #[test]
+    fn expression_errors() {
+        assert_eq!(
+            errors("1 +;"),
+            ["[line 1] Error at ';': Expect expression."]
+        );
+        assert_eq!(
+            errors("1 + 2"),
+            ["[line 1] Error at end: Expect ';' after expression."]
+        );
+        assert_eq!(
+            errors("(1 + 2;"),
+            ["[line 1] Error at ';': Expect ')' after expression."]
+        );
+        assert_eq!(errors(")"), ["[line 1] Error at ')': Expect expression."]);
+        assert_eq!(
+            errors("f(1, 2;"),
+            ["[line 1] Error at ';': Expect ')' after arguments."]
+        );
+        assert_eq!(
+            errors("a.;"),
+            ["[line 1] Error at ';': Expect property name after '.'."]
+        );
+        assert_eq!(
+            errors("a ? b;"),
+            ["[line 1] Error at ';': Expect ':' after then branch of conditional expression."]
+        );
+        assert_eq!(
+            errors("[1, 2;"),
+            ["[line 1] Error at ';': Expect ']' after list elements."]
+        );
+    }
+
+    #[test]
+    fn invalid_assignment_targets() {
+        for source in [
+            "1 = 2;",
+            "a + b = c;",
+            "(a) = b;",
+            "f() = 1;",
+            "a + b += 1;",
+            "++1;",
+            "f()--;",
+        ] {
+            let errors = errors(source);
+
+            assert_eq!(errors.len(), 1, "{source}: {errors:?}");
+            assert!(
+                errors[0].ends_with("Invalid assignment target."),
+                "{source}: {errors:?}"
+            );
+        }
+
+        assert_eq!(
+            errors("a + b = c;"),
+            ["[line 1] Error at '=': Invalid assignment target."]
+        );
+    }
+
+    #[test]
+    fn statement_errors() {
+        assert_eq!(
+            errors("var = 1;"),
+            ["[line 1] Error at '=': Expect variable name."]
+        );
+        assert_eq!(
+            errors("var a = 1"),
+            ["[line 1] Error at end: Expect ';' after variable declaration."]
+        );
+        assert_eq!(
+            errors("if a) b;"),
+            ["[line 1] Error at 'a': Expect '(' after 'if'."]
+        );
+        assert_eq!(
+            errors("{ a;"),
+            ["[line 1] Error at end: Expect '}' after block."]
+        );
+        assert_eq!(
+            errors("print 1"),
+            ["[line 1] Error at end: Expect ';' after value."]
+        );
+        assert_eq!(
+            errors("class { }"),
+            ["[line 1] Error at '{': Expect class name."]
+        );
+        assert_eq!(
+            errors("fun f(a b) {}"),
+            ["[line 1] Error at 'b': Expect ')' after parameters."]
+        );
+    }
+
+    #[test]
+    fn context_errors() {
+        assert_eq!(
+            errors("return 1;"),
+            ["[line 1] Error at 'return': Can't return from top-level code."]
+        );
+        assert_eq!(
+            errors("break;"),
+            ["[line 1] Error at 'break': Can't use 'break' outside of a loop."]
+        );
+        assert_eq!(
+            errors("fun f() { continue; }"),
+            ["[line 1] Error at 'continue': Can't use 'continue' outside of a loop."]
+        );
+        assert_eq!(
+            errors("print this;"),
+            ["[line 1] Error at 'this': Can't use 'this' outside of a class."]
+        );
+        assert_eq!(
+            errors("class A { f() { super.f(); } }"),
+            ["[line 1] Error at 'super': Can't use 'super' in a class with no superclass."]
+        );
+        assert_eq!(
+            errors("class A < A {}"),
+            ["[line 1] Error at 'A': A class can't inherit from itself."]
+        );
+        assert_eq!(
+            errors("class A { init() { return 1; } }"),
+            ["[line 1] Error at 'return': Can't return a value from an initializer."]
+        );
+    }
+
+    #[test]
+    fn scope_errors() {
+        let redeclared =
+            ["[line 1] Error at 'a': Already a variable with this name in this scope."];
+
+        assert_eq!(errors("{ var a = 1; var a = 2; }"), redeclared);
+        assert_eq!(errors("fun f(a, a) {}"), redeclared);
+        assert_eq!(errors("fun f(a) { var a; }"), redeclared);
+        assert_eq!(errors("for (a, a in list) {}"), redeclared);
+        assert_eq!(
+            errors("{ var a = a; }"),
+            ["[line 1] Error at 'a': Can't read local variable in its own initializer."]
+        );
+
+        //globals can be declared again, and shadowed by locals
+        assert!(parse("var a = 1; var a = a; { var a = 2; { var a = 3; } }").is_ok());
+        //an arrow function's parameters aren't reads of the variable being initialized
+        assert!(parse("{ var f = (f) => f; var g = (a, g) => g; }").is_ok());
+        //alternatives can bind the same name
+        assert!(parse("match (x) { [a, 1] | [1, a] => print a; _ => {} }").is_ok());
+    }
+
+    //each name in `(a, b, ...)`, and how many scopes out it was resolved to
+    fn depths(expr: &Expr, names: &mut Vec<(String, Depth)>) {
+        match expr {
+            Expr::Binary(left, Operator::Comma, right, _) => {
+                depths(left, names);
+                depths(right, names);
+            }
+            Expr::Grouping(expr) => depths(expr, names),
+            Expr::Variable(name, depth) => names.push((name.lexeme.to_string(), *depth)),
+            _ => panic!("expected names separated by commas, got {expr:?}"),
+        }
+    }
+
+    //the depths of the names in the first return statement in source
+    fn returned_depths(source: &str) -> Vec<(String, Depth)> {
+        fn find(statements: &[Stmt]) -> Option<&Expr> {
+            statements.iter().find_map(|statement| match statement {
+                Stmt::Return(_, value) => value.as_ref(),
+                Stmt::Block(statements) => find(statements),
+                Stmt::While(_, _, body, _) | Stmt::ForIn(_, _, _, body, _) => {
+                    find(std::slice::from_ref(body))
+                }
+                Stmt::Function(function) => find(&function.body),
+                Stmt::Class(_, _, methods) => methods.iter().find_map(|method| find(&method.body)),
+                _ => None,
+            })
+        }
+
+        let statements = parse(source).unwrap_or_else(|errors| panic!("{errors:?}"));
+        let returned = find(&statements).expect("there's a return statement");
+
+        let mut names = vec![];
+        depths(returned, &mut names);
+        names
+    }
+
+    fn named(depths: &[(&str, Depth)]) -> Vec<(String, Depth)> {
+        depths
+            .iter()
+            .map(|(name, depth)| (name.to_string(), *depth))
+            .collect()
+    }
+
+    #[test]
+    fn variables_resolve_to_where_they_are_declared() {
+        assert_eq!(
+            returned_depths("var a; { var b; fun f(c) { { var d; return (a, b, c, d, e, f); } } }"),
+            named(&[
+                ("a", Depth::Global(3)),
+                ("b", Depth::Local(2)),
+                ("c", Depth::Local(1)),
+                ("d", Depth::Local(0)),
+                ("e", Depth::Global(3)),
+                ("f", Depth::Local(2))
+            ])
+        );
+
+        //a name declared after the function is, even in a scope the function can see, isn't
+        //the one it refers to
+        assert_eq!(
+            returned_depths("{ fun f() { return (a, f); } var a; }"),
+            named(&[("a", Depth::Global(2)), ("f", Depth::Local(1))])
+        );
+
+        //methods are inside a scope for `this`, and one for `super` too in a subclass
+        assert_eq!(
+            returned_depths("class A { m(a) { return (a, A); } }"),
+            named(&[("a", Depth::Local(0)), ("A", Depth::Global(2))])
+        );
+        assert_eq!(
+            returned_depths("class B < A { m(a) { return (a, B); } }"),
+            named(&[("a", Depth::Local(0)), ("B", Depth::Global(3))])
+        );
+
+        //a for loop's initializer gets a scope, and so does each pass of a for-in
+        assert_eq!(
+            returned_depths("fun f() { for (var i = 0; ; ) { return (i, f); } }"),
+            named(&[("i", Depth::Local(1)), ("f", Depth::Global(3))])
+        );
+        assert_eq!(
+            returned_depths("fun f() { for (k, v in map) return (k, v, map); }"),
+            named(&[
+                ("k", Depth::Local(0)),
+                ("v", Depth::Local(0)),
+                ("map", Depth::Global(2))
+            ])
+        );
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let arguments = vec!["a"; 256].join(", ");
+
+        assert_eq!(
+            errors(&format!("f({arguments});")),
+            ["[line 1] Error at 'a': Can't have more than 255 arguments."]
+        );
+        assert!(parse(&format!("f({});", vec!["a"; 255].join(", "))).is_ok());
+
+        assert_eq!(
+            errors(&format!("fun f({arguments}) {{}}")),
+            ["[line 1] Error at 'a': Can't have more than 255 parameters."]
+        );
+    }
+
+    //after an error the parser skips to the next statement, so later errors are reported too
+    #[test]
+    fn reports_errors_from_every_statement() {
+        assert_eq!(
+            errors("var = 1;\nprint 2;\n1 +;\nvar b = 3;\nprint"),
+            [
+                "[line 1] Error at '=': Expect variable name.",
+                "[line 3] Error at ';': Expect expression.",
+                "[line 5] Error at end: Expect expression.",
+            ]
+        );
+    }
+
+    //recovering inside a block means its `}` still closes it, rather than being left over as a
+    //stray top level token
+    #[test]
+    fn recovers_inside_blocks() {
+        assert_eq!(
+            errors("fun f() {\n  var = 1;\n  print 2;\n  1 +;\n}\nprint 3;"),
+            [
+                "[line 2] Error at '=': Expect variable name.",
+                "[line 4] Error at ';': Expect expression.",
+            ]
+        );
+        assert_eq!(
+            errors("{ { a = ; } }\nclass A { m() { this = 1; } }"),
+            [
+                "[line 1] Error at ';': Expect expression.",
+                "[line 2] Error at '=': Invalid assignment target.",
+            ]
+        );
+    }
+
+    #[test]
+    fn error_lines() {
+        assert_eq!(
+            errors("print 1;\n\n\nprint (2;"),
+            ["[line 4] Error at ';': Expect ')' after expression."]
+        );
+    }
+
+    #[test]
+    fn warnings_dont_fail_the_parse() {
+        let mut parser = Parser::from_scanner(Scanner::new("match (1) { 1 => print 1; }"));
+
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.warnings().len(), 1);
+        assert_eq!(
+            parser.warnings()[0].message,
+            "Match has no catch-all arm, so some values won't match any arm."
+        );
+    }
+
+    #[test]
+    fn alternatives_bind_the_same_names() {
+        let different = ["[line 1] Error at '|': Alternatives must bind the same names."];
+
+        assert_eq!(errors("match (x) { [a] | [b] => {} _ => {} }"), different);
+        assert_eq!(
+            errors("match (x) { [a, b] | [a] => {} _ => {} }"),
+            different
+        );
+        assert_eq!(errors("match (x) { 1 | a => {} }"), different);
+        assert_eq!(errors("match (x) { [[a] | 1] => {} _ => {} }"), different);
+
+        //the order they're bound in doesn't matter
+        assert!(parse("match (x) { [a, b] | [b, a, _] => print a + b; _ => {} }").is_ok());
+        assert!(parse("match (x) { 1 | 2 | _ => {} }").is_ok());
+    }
+
+    #[test]
+    fn scan_errors_are_kept_apart() {
+        let mut parser = Parser::from_scanner(Scanner::new("print 1; @ print 2;"));
+
+        assert!(parser.parse().is_ok());
+        assert_eq!(
+            parser
+                .scan_errors()
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            ["[line 1] Error: Unexpected character '@'."]
+        );
+    }
+
+    #[test]
+    fn trailing_expression() {
+        assert!(parse("1 + 2").is_err());
+
+        let mut parser = Parser::from_scanner(Scanner::new("var a = 1; a + 2"));
+        parser.allow_trailing_expression();
+
+        assert!(matches!(
+            parser.parse().unwrap().as_slice(),
+            [Stmt::Var(_, _), Stmt::Expression(_)]
+        ));
+    }
+
+    //printing a parsed expression fully parenthesized and parsing that again gives the same tree
+    #[test]
+    fn property_printed_expressions_parse_back() {
+        let mut seed: u64 = 0x853c_49e6_748f_ea9b;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+
+        const BINARY: &[&str] = &[
+            "+", "-", "*", "/", "~/", "%", "**", "==", "!=", "<", "<=", ">", ">=", "and", "or",
+            "&", "|", "^", "<<", ">>",
+        ];
+        const UNARY: &[&str] = &["-", "!", "~"];
+        const ATOMS: &[&str] = &["1", "2.5", "a", "b", "\"s\"", "nil", "true", "f(a)", "x.y"];
+
+        fn generate(next: &mut dyn FnMut(usize) -> usize, depth: usize) -> String {
+            if depth == 0 {
+                return ATOMS[next(ATOMS.len())].to_string();
+            }
+
+            match next(5) {
+                0 => format!("{} {}", UNARY[next(UNARY.len())], generate(next, depth - 1)),
+                1 => format!("({})", generate(next, depth - 1)),
+                2 => format!(
+                    "{} ? {} : {}",
+                    generate(next, depth - 1),
+                    generate(next, depth - 1),
+                    generate(next, depth - 1)
+                ),
+                _ => format!(
+                    "{} {} {}",
+                    generate(next, depth - 1),
+                    BINARY[next(BINARY.len())],
+                    generate(next, depth - 1)
+                ),
+            }
+        }
+
+        for _ in 0..300 {
+            let depth = next(5);
+            let source = generate(&mut next, depth);
+
+            let tree = expr(&source);
+            let parenthesized = parenthesize(&source);
+
+            assert_eq!(
+                strip_groups(&expr(&parenthesized)),
+                strip_groups(&tree),
+                "{source}"
+            );
+        }
+    }
+
+    //source with every binary, unary and ternary expression wrapped in parentheses, built
+    //from the tree the parser made of it
+    fn parenthesize(source: &str) -> String {
+        let statements = parse(&format!("{source};")).unwrap();
+
+        match statements.as_slice() {
+            [Stmt::Expression(expr)] => unparse(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn unparse(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(left, _, right, operator) => {
+                format!("({} {} {})", unparse(left), operator.lexeme, unparse(right))
+            }
+            Expr::Unary(_, right, operator) => format!("({} {})", operator.lexeme, unparse(right)),
+            Expr::Grouping(expr) => format!("({})", unparse(expr)),
+            Expr::Ternary(condition, then_branch, else_branch) => format!(
+                "({} ? {} : {})",
+                unparse(condition),
+                unparse(then_branch),
+                unparse(else_branch)
+            ),
+            Expr::Literal(literal) => match literal.as_ref() {
+                Literal::String(string) => format!("\"{string}\""),
+                _ => print(expr),
+            },
+            Expr::Call(callee, _, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(unparse).collect();
+                format!("{}({})", unparse(callee), arguments.join(", "))
+            }
+            Expr::Get(object, name) => format!("{}.{}", unparse(object), name.lexeme),
+            _ => print(expr),
+        }
+    }
+
+    //the extra parentheses show up as groups, which don't change what the tree means
+    fn strip_groups(printed: &str) -> String {
+        let mut printed = printed.to_string();
+
+        while let Some(start) = printed.find("(group ") {
+            //drop the `(group ` and its matching `)`
+            let mut depth = 0;
+            let mut end = start;
+
+            for (i, c) in printed[start..].char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+
+                if depth == 0 {
+                    end = start + i;
+                    break;
+                }
+            }
+
+            printed.replace_range(end..=end, "");
+            printed.replace_range(start..start + "(group ".len(), "");
+        }
+
+        printed
+    }
+}
@@ -1,160 +1,395 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
 
-use crate::lox::*;
 use crate::token::*;
 use crate::tokentype::*;
 
+//how many bytes we pull from a reader at a time
+const CHUNK_SIZE: usize = 8 * 1024;
+
 pub struct Scanner<'a> {
-    source: &'a str,
-    tokens: Vec<Token<'a>>,
+    source: Source<'a>,
+    //tokens (and errors) scanned but not yet handed out by next()
+    pending: VecDeque<Result<Token, ScanError>>,
     start: usize,
     current: usize,
+    //bytes already dropped from the front of a reader's buffer, to keep spans absolute
+    offset: usize,
     line: u32,
-    lox: Lox,
-    keywords: HashMap<&'a str, TokenType>,
+    finished: bool,
+    keywords: HashMap<&'static str, TokenType>,
+    keep_trivia: bool,
+    //trivia seen since the last token, waiting for the token it leads
+    leading: Vec<Trivia>,
+    //true while trivia on the same line still belongs to the last token
+    trailing_open: bool,
+}
+
+//a &str source is borrowed as is.
+//a reader source only keeps the bytes of the token being scanned
+enum Source<'a> {
+    Str(&'a str),
+    Reader {
+        reader: Box<dyn Read + 'a>,
+        buffer: Vec<u8>,
+        exhausted: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn new(line: u32, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
 }
 
+impl std::error::Error for ScanError {}
+
 //TODO: fix indexing
 
 // TODO: pass correct literals...how are they different from lexemes?
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
+        Self::with_source(Source::Str(source))
+    }
+
+    //reads the source in chunks as tokens are pulled, so a big file is never held in memory whole
+    pub fn from_reader<R: Read + 'a>(reader: R) -> Self {
+        Self::with_source(Source::Reader {
+            reader: Box::new(reader),
+            buffer: vec![],
+            exhausted: false,
+        })
+    }
+
+    fn with_source(source: Source<'a>) -> Self {
         let mut keywords = HashMap::new();
         Self::build_keywords(&mut keywords);
 
         Self {
             source,
-            tokens: vec![],
+            pending: VecDeque::new(),
             start: 0,
             current: 0,
+            offset: 0,
             line: 1,
-            lox: Lox::new(),
+            finished: false,
             keywords,
+            keep_trivia: false,
+            leading: vec![],
+            trailing_open: false,
         }
     }
 
-    fn build_keywords(keywords: &mut HashMap<&str, TokenType>) {
-        keywords.insert(&"and", TokenType::And);
-        keywords.insert(&"class", TokenType::Class);
-        keywords.insert(&"else", TokenType::Else);
-        keywords.insert(&"false", TokenType::False);
-        keywords.insert(&"for", TokenType::For);
-        keywords.insert(&"fun", TokenType::Fun);
-        keywords.insert(&"if", TokenType::If);
-        keywords.insert(&"nil", TokenType::Nil);
-        keywords.insert(&"or", TokenType::Or);
-        keywords.insert(&"print", TokenType::Print);
-        keywords.insert(&"return", TokenType::Return);
-        keywords.insert(&"super", TokenType::Super);
-        keywords.insert(&"this", TokenType::This);
-        keywords.insert(&"true", TokenType::True);
-        keywords.insert(&"var", TokenType::Var);
-        keywords.insert(&"while", TokenType::While);
-    }
-
-    pub fn scan_tokens(mut self) -> Vec<Token<'a>> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+    //lossless mode: whitespace, comments and skipped bad input are attached to the tokens
+    //instead of being thrown away, so concatenating Token::full_text() gives back the source.
+    //trivia up to the end of a token's line is trailing, everything else leads the next token
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
+    }
+
+    fn build_keywords(keywords: &mut HashMap<&'static str, TokenType>) {
+        keywords.insert("and", TokenType::And);
+        keywords.insert("as", TokenType::As);
+        keywords.insert("break", TokenType::Break);
+        keywords.insert("catch", TokenType::Catch);
+        keywords.insert("class", TokenType::Class);
+        keywords.insert("continue", TokenType::Continue);
+        keywords.insert("else", TokenType::Else);
+        keywords.insert("export", TokenType::Export);
+        keywords.insert("false", TokenType::False);
+        keywords.insert("finally", TokenType::Finally);
+        keywords.insert("for", TokenType::For);
+        keywords.insert("fun", TokenType::Fun);
+        keywords.insert("if", TokenType::If);
+        keywords.insert("import", TokenType::Import);
+        keywords.insert("in", TokenType::In);
+        keywords.insert("match", TokenType::Match);
+        keywords.insert("nil", TokenType::Nil);
+        keywords.insert("or", TokenType::Or);
+        keywords.insert("print", TokenType::Print);
+        keywords.insert("return", TokenType::Return);
+        keywords.insert("super", TokenType::Super);
+        keywords.insert("this", TokenType::This);
+        keywords.insert("throw", TokenType::Throw);
+        keywords.insert("true", TokenType::True);
+        keywords.insert("try", TokenType::Try);
+        keywords.insert("var", TokenType::Var);
+        keywords.insert("while", TokenType::While);
+    }
+
+    //returns every error found rather than stopping at the first one
+    pub fn scan_tokens(self) -> Result<Vec<Token>, Vec<ScanError>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
         }
 
-        self.tokens
-            .push(Token::new(Box::new(TokenType::Eof), &"", &"", self.line));
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
 
-        self.tokens
+    fn is_at_end(&mut self) -> bool {
+        self.byte_at(self.current).is_none()
     }
-    //TODO: should this be self.current + 1? otherwise it's always less than len()? bc 0 index while len >= 1
-    fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+
+    //the byte at `index` in the current buffer, reading more from the reader if needed
+    fn byte_at(&mut self, index: usize) -> Option<u8> {
+        match &mut self.source {
+            Source::Str(source) => source.as_bytes().get(index).copied(),
+            Source::Reader {
+                reader,
+                buffer,
+                exhausted,
+            } => {
+                while index >= buffer.len() && !*exhausted {
+                    let mut chunk = [0; CHUNK_SIZE];
+
+                    match reader.read(&mut chunk) {
+                        Ok(0) => *exhausted = true,
+                        Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+                        Err(err) => {
+                            *exhausted = true;
+                            self.pending.push_back(Err(ScanError::new(
+                                self.line,
+                                format!("Error reading source: {err}"),
+                            )));
+                        }
+                    }
+                }
+
+                buffer.get(index).copied()
+            }
+        }
+    }
+
+    fn text(&self, from: usize, to: usize) -> Arc<str> {
+        match &self.source {
+            Source::Str(source) => Arc::from(&source[from..to]),
+            Source::Reader { buffer, .. } => Arc::from(String::from_utf8_lossy(&buffer[from..to])),
+        }
+    }
+
+    //drops the bytes of tokens that have already been handed out
+    fn discard_scanned(&mut self) {
+        if let Source::Reader { buffer, .. } = &mut self.source {
+            if self.current >= CHUNK_SIZE {
+                buffer.drain(..self.current);
+                self.offset += self.current;
+                self.current = 0;
+            }
+        }
     }
 
     fn scan_token(&mut self) {
         let c = self.advance();
+
+        if let Some(kind) = self.trivia(c) {
+            return self.add_trivia(kind);
+        }
+
         match c {
-            '(' => self.add_token(Box::new(TokenType::LeftParen), &""),
-            ')' => self.add_token(Box::new(TokenType::RightParen), &""),
-            '{' => self.add_token(Box::new(TokenType::LeftBrace), &""),
-            '}' => self.add_token(Box::new(TokenType::RightBrace), &""),
-            ',' => self.add_token(Box::new(TokenType::Comma), &""),
-            '.' => self.add_token(Box::new(TokenType::Dot), &""),
-            '-' => self.add_token(Box::new(TokenType::Minus), &""),
-            '+' => self.add_token(Box::new(TokenType::Plus), &""),
-            ';' => self.add_token(Box::new(TokenType::Semicolon), &""),
-            '*' => self.add_token(Box::new(TokenType::Star), &""),
+            '(' => self.add_token(Box::new(TokenType::LeftParen), ""),
+            ')' => self.add_token(Box::new(TokenType::RightParen), ""),
+            '{' => self.add_token(Box::new(TokenType::LeftBrace), ""),
+            '}' => self.add_token(Box::new(TokenType::RightBrace), ""),
+            '[' => self.add_token(Box::new(TokenType::LeftBracket), ""),
+            ']' => self.add_token(Box::new(TokenType::RightBracket), ""),
+            ',' => self.add_token(Box::new(TokenType::Comma), ""),
+            '.' => self.add_token(Box::new(TokenType::Dot), ""),
+            '?' => self.add_token(Box::new(TokenType::Question), ""),
+            ':' => self.add_token(Box::new(TokenType::Colon), ""),
+            '-' => {
+                if self.matching('=') {
+                    self.add_token(Box::new(TokenType::MinusEqual), "")
+                } else if self.matching('-') {
+                    self.add_token(Box::new(TokenType::MinusMinus), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Minus), "")
+                }
+            }
+            '+' => {
+                if self.matching('=') {
+                    self.add_token(Box::new(TokenType::PlusEqual), "")
+                } else if self.matching('+') {
+                    self.add_token(Box::new(TokenType::PlusPlus), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Plus), "")
+                }
+            }
+            ';' => self.add_token(Box::new(TokenType::Semicolon), ""),
+            '%' => {
+                if self.matching('=') {
+                    self.add_token(Box::new(TokenType::PercentEqual), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Percent), "")
+                }
+            }
+            '&' => self.add_token(Box::new(TokenType::Ampersand), ""),
+            '|' => self.add_token(Box::new(TokenType::Pipe), ""),
+            '^' => self.add_token(Box::new(TokenType::Caret), ""),
+            //integer division is `~/` rather than `//`, so that `//` is always a comment
+            '~' => {
+                if self.matching('/') {
+                    self.add_token(Box::new(TokenType::TildeSlash), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Tilde), "")
+                }
+            }
+            '*' => {
+                if self.matching('*') {
+                    self.add_token(Box::new(TokenType::StarStar), "")
+                } else if self.matching('=') {
+                    self.add_token(Box::new(TokenType::StarEqual), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Star), "")
+                }
+            }
+            '/' => {
+                if self.matching('=') {
+                    self.add_token(Box::new(TokenType::SlashEqual), "")
+                } else {
+                    self.add_token(Box::new(TokenType::Slash), "")
+                }
+            }
             '!' => {
                 if self.matching('=') {
-                    self.add_token(Box::new(TokenType::BangEqual), &"")
+                    self.add_token(Box::new(TokenType::BangEqual), "")
                 } else {
-                    self.add_token(Box::new(TokenType::Bang), &"")
+                    self.add_token(Box::new(TokenType::Bang), "")
                 }
             }
             '=' => {
                 if self.matching('=') {
-                    self.add_token(Box::new(TokenType::EqualEqual), &""[..])
+                    self.add_token(Box::new(TokenType::EqualEqual), "")
+                } else if self.matching('>') {
+                    self.add_token(Box::new(TokenType::Arrow), "")
                 } else {
-                    self.add_token(Box::new(TokenType::Equal), &""[..])
+                    self.add_token(Box::new(TokenType::Equal), "")
                 }
             }
             '<' => {
                 if self.matching('=') {
-                    self.add_token(Box::new(TokenType::LessEqual), &""[..])
+                    self.add_token(Box::new(TokenType::LessEqual), "")
+                } else if self.matching('<') {
+                    self.add_token(Box::new(TokenType::LessLess), "")
                 } else {
-                    self.add_token(Box::new(TokenType::Less), &""[..])
+                    self.add_token(Box::new(TokenType::Less), "")
                 }
             }
             '>' => {
                 if self.matching('=') {
-                    self.add_token(Box::new(TokenType::GreaterEqual), &""[..])
+                    self.add_token(Box::new(TokenType::GreaterEqual), "")
+                } else if self.matching('>') {
+                    self.add_token(Box::new(TokenType::GreaterGreater), "")
                 } else {
-                    self.add_token(Box::new(TokenType::Greater), &""[..])
+                    self.add_token(Box::new(TokenType::Greater), "")
                 }
             }
-            '/' => {
-                // if we have double '//' then it's a comment, scan the whole line
-                //but don't consume anything.
-
-                //TODO: below there's support for multi line comments. seems to loop infintely
-
-                if self.matching('/') {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-                } else if self.matching('*') {
-                    // if self.peek() == '\n' {
-                    //     self.line += 1;
-                    // }
-
-                    while (self.peek() != '*' && self.peek_next() != '/') && !self.is_at_end() {
-                        self.advance();
-                    }
-
-                    if self.peek() == '*' && self.peek_next() == '/' {
-                        self.advance();
-                        self.advance();
-                    }
-                } else {
-                    self.add_token(Box::new(TokenType::Slash), &""[..])
-                }
-            }
-            ' ' => (),
-            '\r' => (),
-            '\t' => (),
-            '\n' => self.line += 1,
             '"' => {
                 self.string();
             }
 
             _ => {
                 if Self::is_digit(c) {
-                    self.number();
+                    self.number(c);
                 } else if Self::is_alpha(c) {
                     self.identifier();
                 } else {
-                    // self.lox.error(self.line, &"Unexpected character"[..]);
-                    eprintln!("Unexpected character in scan_token()");
+                    self.error(format!("Unexpected character '{c}'."));
+                }
+            }
+        }
+    }
+
+    //consumes the rest of a whitespace run or comment starting with `c`.
+    //returns None if `c` doesn't start trivia
+    fn trivia(&mut self, c: char) -> Option<TriviaKind> {
+        match c {
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+
+                Some(TriviaKind::Whitespace)
+            }
+            '\n' => {
+                self.line += 1;
+
+                Some(TriviaKind::Newline)
+            }
+            '/' if self.matching('/') => {
+                while self.peek() != '\n' && !self.is_at_end() {
+                    self.advance();
+                }
+
+                Some(TriviaKind::LineComment)
+            }
+            '/' if self.matching('*') => {
+                while !self.is_at_end() {
+                    if self.peek() == '*' && self.peek_next() == '/' {
+                        break;
+                    }
+
+                    if self.advance() == '\n' {
+                        self.line += 1;
+                    }
+                }
+
+                //not self.error(), the comment is still kept as trivia rather than skipped
+                if self.is_at_end() {
+                    let error =
+                        ScanError::new(self.line, String::from("Unterminated block comment."));
+                    self.pending.push_back(Err(error));
+                } else {
+                    self.advance();
+                    self.advance();
                 }
+
+                Some(TriviaKind::BlockComment)
             }
+            _ => None,
+        }
+    }
+
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        if !self.keep_trivia {
+            return;
+        }
+
+        let trivia = Trivia::new(kind, self.text(self.start, self.current));
+
+        if kind == TriviaKind::Newline {
+            self.trailing_open = false;
+        }
+
+        match self.pending.back_mut() {
+            Some(Ok(token)) if self.trailing_open => token.trailing_trivia.push(trivia),
+            _ => self.leading.push(trivia),
         }
     }
 
@@ -163,43 +398,118 @@ impl<'a> Scanner<'a> {
             self.advance();
         }
 
-        let text = &self.source[self.start..self.current];
+        let text = self.text(self.start, self.current);
 
-        match self.keywords.get(text) {
-            Some(token_type) => self.add_token(Box::new(*token_type), &""),
-            None => self.add_token(Box::new(TokenType::Identifier), &""),
+        match self.keywords.get(text.as_ref()) {
+            Some(token_type) => self.add_token(Box::new(*token_type), ""),
+            None => self.add_token(Box::new(TokenType::Identifier), ""),
         }
     }
 
-    fn number(&mut self) {
-        while Self::is_digit(self.peek()) {
+    fn number(&mut self, first: char) {
+        if first == '0' {
+            match self.peek() {
+                'x' | 'X' => return self.radix_number(16, "hex"),
+                'b' | 'B' => return self.radix_number(2, "binary"),
+                'o' | 'O' => return self.radix_number(8, "octal"),
+                _ => (),
+            }
+        }
+
+        self.digits();
+
+        if self.peek() == '.' && Self::is_digit(self.peek_next()) {
             self.advance();
+            self.digits();
         }
 
-        if self.peek() != '.' && Self::is_digit(self.peek_next()) {
+        if self.peek() == 'e' || self.peek() == 'E' {
             self.advance();
 
-            while Self::is_digit(self.peek()) {
+            if self.peek() == '+' || self.peek() == '-' {
                 self.advance();
             }
+
+            if !Self::is_digit(self.peek()) {
+                let text = self.text(self.start, self.current);
+                return self.error(format!("Expected digits in exponent of '{text}'."));
+            }
+
+            self.digits();
         }
 
-        self.add_token(
-            Box::new(TokenType::Number),
-            &self.source[self.start..self.current],
-        );
+        //catch things like `12abc` or `1.5f` instead of splitting them into two tokens
+        if Self::is_alpha(self.peek()) {
+            while Self::is_alphanumeric(self.peek()) {
+                self.advance();
+            }
+
+            let text = self.text(self.start, self.current);
+            return self.error(format!("Invalid number literal '{text}'."));
+        }
+
+        self.add_number(10)
+    }
+
+    //handles 0x, 0b and 0o literals. the prefix is still unconsumed when we get here
+    fn radix_number(&mut self, radix: u32, name: &str) {
+        self.advance();
+
+        while Self::is_alphanumeric(self.peek()) {
+            self.advance();
+        }
+
+        let text = self.text(self.start, self.current);
+        let digits = &text[2..];
+
+        if digits.is_empty() {
+            return self.error(format!("Expected {name} digits after '{}'.", &text[..2]));
+        }
+
+        if let Some(bad) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+            return self.error(format!("Invalid digit '{bad}' in {name} literal '{text}'."));
+        }
+
+        self.add_number(radix)
+    }
+
+    //consumes a run of decimal digits, allowing '_' as a separator
+    fn digits(&mut self) {
+        while Self::is_digit(self.peek()) || self.peek() == '_' {
+            self.advance();
+        }
     }
 
-    fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
+    //separators have to sit between two digits, so `_1`, `1_`, `1__0` and `0x_f` are all rejected
+    fn add_number(&mut self, radix: u32) {
+        let text = self.text(self.start, self.current);
+        let bytes = text.as_bytes();
+
+        for (i, byte) in bytes.iter().enumerate() {
+            if *byte != b'_' {
+                continue;
+            }
+
+            let before = bytes[i - 1] as char;
+            let after = bytes.get(i + 1).map_or('\0', |b| *b as char);
+
+            if !before.is_digit(radix) || !after.is_digit(radix) {
+                return self.error(format!(
+                    "Digit separator '_' must be between digits in '{text}'."
+                ));
+            }
         }
 
-        self.source.as_bytes()[self.current + 1] as char
+        self.add_token(Box::new(TokenType::Number), text);
+    }
+
+    fn peek_next(&mut self) -> char {
+        self.byte_at(self.current + 1)
+            .map_or('\0', |byte| byte as char)
     }
 
     fn is_alpha(c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn is_alphanumeric(c: char) -> bool {
@@ -214,23 +524,18 @@ impl<'a> Scanner<'a> {
             self.advance();
         }
 
-        //TODO: pass token, here I'm passing self.line as token. WRONG
         if self.is_at_end() {
-            // self.lox.error(self.advance(), "unterminated string");
+            return self.error(String::from("Unterminated string."));
         }
 
         self.advance();
         //Trim surrounding quotes
-        let value = &self.source[self.start + 1..self.current - 1];
+        let value = self.text(self.start + 1, self.current - 1);
         self.add_token(Box::new(TokenType::String), value);
     }
 
     fn matching(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-        //trying to index a string at idx current
-        if self.source.as_bytes()[self.current] as char != expected {
+        if self.peek() != expected {
             return false;
         }
 
@@ -238,26 +543,770 @@ impl<'a> Scanner<'a> {
         true
     }
 
-    fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.as_bytes()[self.current] as char
+    fn peek(&mut self) -> char {
+        self.byte_at(self.current).map_or('\0', |byte| byte as char)
     }
 
     fn is_digit(c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
+    //consumes a whole character, so `current` never ends up inside a multibyte one and the
+    //source can always be sliced there
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source.as_bytes()[self.current - 1] as char
+        let first = match self.byte_at(self.current) {
+            Some(byte) => byte,
+            None => {
+                self.current += 1;
+                return '\0';
+            }
+        };
+
+        let width = match first {
+            0xf0.. => 4,
+            0xe0.. => 3,
+            0xc0.. => 2,
+            _ => 1,
+        };
+
+        let mut bytes = vec![first];
+
+        while bytes.len() < width {
+            match self.byte_at(self.current + bytes.len()) {
+                Some(byte @ 0x80..=0xbf) => bytes.push(byte),
+                _ => break,
+            }
+        }
+
+        self.current += bytes.len();
+
+        //only a reader source can hold invalid utf-8, and text() reads that lossily anyway
+        std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 
-    fn add_token(&mut self, token_type: Box<TokenType>, literal: &'a str) {
-        let text = &self.source[self.start as usize..self.current as usize];
+    fn add_token(&mut self, token_type: Box<TokenType>, literal: impl Into<Arc<str>>) {
+        let text = self.text(self.start, self.current);
+        let mut token = Token::new(token_type, text, literal, self.line);
+        token.span = Span::new(self.offset + self.start, self.offset + self.current);
+
+        if self.keep_trivia {
+            token.leading_trivia = std::mem::take(&mut self.leading);
+            self.trailing_open = true;
+        }
+
+        self.pending.push_back(Ok(token));
+    }
+
+    //in lossless mode the text we gave up on is kept as skipped trivia
+    fn error(&mut self, message: String) {
+        if self.keep_trivia && self.current > self.start {
+            let text = self.text(self.start, self.current);
+            self.leading.push(Trivia::new(TriviaKind::Skipped, text));
+            self.trailing_open = false;
+        }
+
+        self.pending
+            .push_back(Err(ScanError::new(self.line, message)));
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token, ScanError>;
+
+    //scans just enough of the source to produce the next token or error.
+    //the final item is always the Eof token
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            //a token isn't handed out until we know the rest of its line is not trailing trivia
+            let awaiting_trivia = self.trailing_open && self.pending.len() == 1;
 
-        self.tokens
-            .push(Token::new(token_type, &text, &literal, self.line));
+            if !awaiting_trivia {
+                if let Some(result) = self.pending.pop_front() {
+                    return Some(result);
+                }
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            self.discard_scanned();
+            self.start = self.current;
+
+            if self.is_at_end() {
+                self.finished = true;
+                self.add_token(Box::new(TokenType::Eof), "");
+                self.trailing_open = false;
+            } else {
+                self.scan_token();
+            }
+        }
+    }
+}
+
+//turns a lexeme the scanner has already validated into its value.
+//radix literals are accumulated as f64 so huge hex constants lose precision instead of overflowing
+pub fn parse_number(text: &str) -> Option<f64> {
+    let digits: String = text.chars().filter(|c| *c != '_').collect();
+
+    let radix = match digits.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0b") | Some("0B") => 2,
+        Some("0o") | Some("0O") => 8,
+        _ => return digits.parse().ok(),
+    };
+
+    digits[2..].chars().try_fold(0.0, |value, c| {
+        c.to_digit(radix)
+            .map(|digit| value * radix as f64 + digit as f64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::*;
+
+    fn scan(source: &str) -> Vec<Token> {
+        Scanner::new(source)
+            .scan_tokens()
+            .unwrap_or_else(|errors| panic!("{source:?} didn't scan: {errors:?}"))
+    }
+
+    //token types, Eof left off
+    fn types(source: &str) -> Vec<TokenType> {
+        let tokens = scan(source);
+
+        tokens[..tokens.len() - 1]
+            .iter()
+            .map(|token| *token.token_type)
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        match Scanner::new(source).scan_tokens() {
+            Ok(tokens) => panic!("{source:?} scanned without errors: {tokens:?}"),
+            Err(errors) => errors.into_iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn single_character_tokens() {
+        use TokenType::*;
+
+        let expected = [
+            ("(", LeftParen),
+            (")", RightParen),
+            ("{", LeftBrace),
+            ("}", RightBrace),
+            ("[", LeftBracket),
+            ("]", RightBracket),
+            (",", Comma),
+            (".", Dot),
+            ("?", Question),
+            (":", Colon),
+            ("-", Minus),
+            ("+", Plus),
+            (";", Semicolon),
+            ("/", Slash),
+            ("*", Star),
+            ("%", Percent),
+            ("&", Ampersand),
+            ("|", Pipe),
+            ("^", Caret),
+            ("~", Tilde),
+            ("!", Bang),
+            ("=", Equal),
+            ("<", Less),
+            (">", Greater),
+        ];
+
+        for (source, token_type) in expected {
+            assert_eq!(types(source), [token_type], "{source}");
+        }
+    }
+
+    #[test]
+    fn two_character_tokens() {
+        use TokenType::*;
+
+        let expected = [
+            ("-=", MinusEqual),
+            ("--", MinusMinus),
+            ("+=", PlusEqual),
+            ("++", PlusPlus),
+            ("/=", SlashEqual),
+            ("*=", StarEqual),
+            ("**", StarStar),
+            ("%=", PercentEqual),
+            ("~/", TildeSlash),
+            ("!=", BangEqual),
+            ("==", EqualEqual),
+            ("=>", Arrow),
+            ("<=", LessEqual),
+            ("<<", LessLess),
+            (">=", GreaterEqual),
+            (">>", GreaterGreater),
+        ];
+
+        for (source, token_type) in expected {
+            assert_eq!(types(source), [token_type], "{source}");
+        }
+    }
+
+    #[test]
+    fn longest_operator_wins() {
+        use TokenType::*;
+
+        assert_eq!(types("==="), [EqualEqual, Equal]);
+        assert_eq!(types("!=="), [BangEqual, Equal]);
+        assert_eq!(types("<<="), [LessLess, Equal]);
+        assert_eq!(types("***"), [StarStar, Star]);
+        assert_eq!(types("+++"), [PlusPlus, Plus]);
+        assert_eq!(types("= ="), [Equal, Equal]);
+    }
+
+    #[test]
+    fn keywords() {
+        let keywords = [
+            ("and", TokenType::And),
+            ("as", TokenType::As),
+            ("break", TokenType::Break),
+            ("catch", TokenType::Catch),
+            ("class", TokenType::Class),
+            ("continue", TokenType::Continue),
+            ("else", TokenType::Else),
+            ("export", TokenType::Export),
+            ("false", TokenType::False),
+            ("finally", TokenType::Finally),
+            ("for", TokenType::For),
+            ("fun", TokenType::Fun),
+            ("if", TokenType::If),
+            ("import", TokenType::Import),
+            ("in", TokenType::In),
+            ("match", TokenType::Match),
+            ("nil", TokenType::Nil),
+            ("or", TokenType::Or),
+            ("print", TokenType::Print),
+            ("return", TokenType::Return),
+            ("super", TokenType::Super),
+            ("this", TokenType::This),
+            ("throw", TokenType::Throw),
+            ("true", TokenType::True),
+            ("try", TokenType::Try),
+            ("var", TokenType::Var),
+            ("while", TokenType::While),
+        ];
+
+        for (source, token_type) in keywords {
+            assert_eq!(types(source), [token_type], "{source}");
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        for source in [
+            "andy", "formless", "fo", "_", "_123", "_abc", "ab123", "classy", "For",
+        ] {
+            let tokens = scan(source);
+
+            assert_eq!(*tokens[0].token_type, TokenType::Identifier, "{source}");
+            assert_eq!(&*tokens[0].lexeme, source);
+            assert_eq!(tokens[0].symbol, Some(Symbol::intern(source)));
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        let expected = [
+            ("0", 0.0),
+            ("123", 123.0),
+            ("123.456", 123.456),
+            ("1e3", 1000.0),
+            ("2.5E-1", 0.25),
+            ("1e+2", 100.0),
+            ("1_000_000", 1_000_000.0),
+            ("0xff", 255.0),
+            ("0XFF", 255.0),
+            ("0b1010", 10.0),
+            ("0o17", 15.0),
+            ("0xdead_beef", 3_735_928_559.0),
+        ];
+
+        for (source, value) in expected {
+            let tokens = scan(source);
+
+            assert_eq!(*tokens[0].token_type, TokenType::Number, "{source}");
+            assert_eq!(&*tokens[0].lexeme, source);
+            assert_eq!(parse_number(&tokens[0].literal), Some(value), "{source}");
+        }
+    }
+
+    //a dot only belongs to a number if a digit follows it
+    #[test]
+    fn dots_around_numbers() {
+        use TokenType::*;
+
+        assert_eq!(types("123."), [Number, Dot]);
+        assert_eq!(types(".123"), [Dot, Number]);
+        assert_eq!(types("1.2.3"), [Number, Dot, Number]);
+        assert_eq!(types("-5"), [Minus, Number]);
+    }
+
+    #[test]
+    fn number_errors() {
+        assert_eq!(
+            errors("1e"),
+            ["[line 1] Error: Expected digits in exponent of '1e'."]
+        );
+        assert_eq!(
+            errors("12abc"),
+            ["[line 1] Error: Invalid number literal '12abc'."]
+        );
+        assert_eq!(
+            errors("0x"),
+            ["[line 1] Error: Expected hex digits after '0x'."]
+        );
+        assert_eq!(
+            errors("0b102"),
+            ["[line 1] Error: Invalid digit '2' in binary literal '0b102'."]
+        );
+        assert_eq!(
+            errors("1__0"),
+            ["[line 1] Error: Digit separator '_' must be between digits in '1__0'."]
+        );
+        assert_eq!(
+            errors("1_"),
+            ["[line 1] Error: Digit separator '_' must be between digits in '1_'."]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let tokens = scan(r#""" "hello world" "a // b""#);
+
+        let literals: Vec<&str> = tokens[..3].iter().map(|token| &*token.literal).collect();
+        assert_eq!(literals, ["", "hello world", "a // b"]);
+
+        //the lexeme keeps the quotes
+        assert_eq!(&*tokens[1].lexeme, "\"hello world\"");
+        assert_eq!(tokens[1].symbol, Some(Symbol::intern("hello world")));
+    }
+
+    #[test]
+    fn multiline_string() {
+        let tokens = scan("\"one\ntwo\nthree\" after");
+
+        assert_eq!(&*tokens[0].literal, "one\ntwo\nthree");
+        //a token's line is where it ends
+        assert_eq!(tokens[0].line, 3);
+        assert_eq!(tokens[1].line, 3);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert_eq!(
+            errors("\"no close quote"),
+            ["[line 1] Error: Unterminated string."]
+        );
+        assert_eq!(errors("\"a\nb"), ["[line 2] Error: Unterminated string."]);
+    }
+
+    #[test]
+    fn comments() {
+        use TokenType::*;
+
+        assert_eq!(types("// just a comment"), []);
+        assert_eq!(
+            types("a;\n// comment\nb"),
+            [Identifier, Semicolon, Identifier]
+        );
+        assert_eq!(types("a /* block */ b"), [Identifier, Identifier]);
+        assert_eq!(types("/* one\ntwo */ a"), [Identifier]);
+        assert_eq!(types("print 1; // trailing"), [Print, Number, Semicolon]);
+    }
+
+    #[test]
+    fn block_comments_count_lines() {
+        let tokens = scan("/* one\ntwo\n*/ a");
+
+        assert_eq!(tokens[0].line, 3);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        assert_eq!(
+            errors("a /* never\nclosed"),
+            ["[line 2] Error: Unterminated block comment."]
+        );
+    }
+
+    //`//` is a comment wherever it appears. integer division is `~/`
+    #[test]
+    fn slash_slash_is_always_a_comment() {
+        use TokenType::*;
+
+        assert_eq!(types("7 // 2"), [Number]);
+        assert_eq!(types("print 1 // comment"), [Print, Number]);
+        assert_eq!(
+            types("f(a // note\n)"),
+            [Identifier, LeftParen, Identifier, RightParen]
+        );
+        assert_eq!(types("7 ~/ 2"), [Number, TildeSlash, Number]);
+        assert_eq!(types("~ /"), [Tilde, Slash]);
+        assert_eq!(types("~~/"), [Tilde, TildeSlash]);
+    }
+
+    #[test]
+    fn whitespace_is_skipped() {
+        use TokenType::*;
+
+        assert_eq!(types(" \t\r\n a \t\r\n b "), [Identifier, Identifier]);
+        assert_eq!(types(""), []);
+    }
+
+    #[test]
+    fn lines_are_counted() {
+        let tokens = scan("a\nb\n\nc\r\nd");
+        let lines: Vec<u32> = tokens.iter().map(|token| token.line).collect();
+
+        assert_eq!(lines, [1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let tokens = scan("var ab = \"é\";");
+        let spans: Vec<Span> = tokens.iter().map(|token| token.span).collect();
+
+        assert_eq!(
+            spans,
+            [
+                Span::new(0, 3),
+                Span::new(4, 6),
+                Span::new(7, 8),
+                Span::new(9, 13),
+                Span::new(13, 14),
+                Span::new(14, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_with_eof() {
+        for source in ["", "a", "a\n", "// comment\n\n"] {
+            let tokens = scan(source);
+            let eof = tokens.last().expect("there's always an Eof");
+
+            assert_eq!(*eof.token_type, TokenType::Eof, "{source:?}");
+            assert_eq!(&*eof.lexeme, "");
+            assert_eq!(eof.line as usize, source.matches('\n').count() + 1);
+        }
+    }
+
+    #[test]
+    fn unexpected_characters() {
+        assert_eq!(errors("@"), ["[line 1] Error: Unexpected character '@'."]);
+        assert_eq!(
+            errors("a\n#"),
+            ["[line 2] Error: Unexpected character '#'."]
+        );
+    }
+
+    //scanning carries on after an error so every one of them is reported
+    #[test]
+    fn reports_every_error() {
+        assert_eq!(
+            errors("@ a\n# b\n\"open"),
+            [
+                "[line 1] Error: Unexpected character '@'.",
+                "[line 2] Error: Unexpected character '#'.",
+                "[line 3] Error: Unterminated string.",
+            ]
+        );
+
+        let results: Vec<_> = Scanner::new("a @ b").collect();
+        let tokens: Vec<TokenType> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|token| *token.token_type)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            [TokenType::Identifier, TokenType::Identifier, TokenType::Eof]
+        );
+    }
+
+    //a reader source gives the same tokens as a string, even across chunk boundaries
+    #[test]
+    fn reader_matches_str() {
+        let source = "var long_name = \"a string\"; // comment\n".repeat(CHUNK_SIZE / 16);
+
+        let from_str = scan(&source);
+        let from_reader = Scanner::from_reader(source.as_bytes())
+            .scan_tokens()
+            .expect("scans");
+
+        assert_eq!(from_str, from_reader);
+    }
+
+    #[test]
+    fn trivia_round_trips() {
+        let source = "  var a = 1; // one\n/* two */\n\tprint a @ ;\n";
+
+        let text: String = Scanner::new(source)
+            .with_trivia()
+            .filter_map(Result::ok)
+            .map(|token| token.full_text())
+            .collect();
+
+        assert_eq!(text, source);
+    }
+
+    #[test]
+    fn non_ascii() {
+        let tokens = scan("print \"héllo → 世界\"; // ünïcode\n/* ✓ */ x");
+
+        assert_eq!(&*tokens[1].literal, "héllo → 世界");
+        assert_eq!(*tokens[3].token_type, TokenType::Identifier);
+
+        assert_eq!(
+            errors("var x = 1; é"),
+            ["[line 1] Error: Unexpected character 'é'."]
+        );
+        assert_eq!(
+            errors("a 世 b 🦀"),
+            [
+                "[line 1] Error: Unexpected character '世'.",
+                "[line 1] Error: Unexpected character '🦀'.",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_trivia_round_trips() {
+        for source in [
+            "var x = 1; é",
+            "é",
+            "print \"é\"; /* ü */ // 世界\n🦀 x",
+            "\"unterminated é",
+            "/* unterminated ✓",
+        ] {
+            let text: String = Scanner::new(source)
+                .with_trivia()
+                .filter_map(Result::ok)
+                .map(|token| token.full_text())
+                .collect();
+
+            assert_eq!(text, source);
+        }
+    }
+
+    //a reader can split a character across chunks
+    #[test]
+    fn non_ascii_from_reader() {
+        let source = format!("{}\"é\" é", " ".repeat(CHUNK_SIZE - 1));
+
+        let results: Vec<_> = Scanner::from_reader(source.as_bytes())
+            .with_trivia()
+            .collect();
+        let text: String = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|token| token.full_text())
+            .collect();
+
+        assert_eq!(text, source);
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Ok(token) if &*token.literal == "é")));
+    }
+
+    //a small xorshift generator, so the property tests below are random but repeatable
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())]
+        }
+
+        //between 1 and max characters from chars
+        fn run(&mut self, chars: &[u8], max: usize) -> String {
+            let len = 1 + self.below(max);
+            (0..len).map(|_| self.pick(chars) as char).collect()
+        }
+    }
+
+    //the lexeme and type of a random token
+    fn random_token(rng: &mut Rng) -> (String, TokenType) {
+        use TokenType::*;
+
+        const FIXED: &[(&str, TokenType)] = &[
+            ("(", LeftParen),
+            (")", RightParen),
+            ("{", LeftBrace),
+            ("}", RightBrace),
+            ("[", LeftBracket),
+            ("]", RightBracket),
+            (",", Comma),
+            (".", Dot),
+            ("?", Question),
+            (":", Colon),
+            ("-", Minus),
+            ("-=", MinusEqual),
+            ("--", MinusMinus),
+            ("+", Plus),
+            ("+=", PlusEqual),
+            ("++", PlusPlus),
+            (";", Semicolon),
+            ("/", Slash),
+            ("/=", SlashEqual),
+            ("*", Star),
+            ("*=", StarEqual),
+            ("**", StarStar),
+            ("%", Percent),
+            ("%=", PercentEqual),
+            ("&", Ampersand),
+            ("|", Pipe),
+            ("^", Caret),
+            ("~", Tilde),
+            ("~/", TildeSlash),
+            ("!", Bang),
+            ("!=", BangEqual),
+            ("=", Equal),
+            ("==", EqualEqual),
+            ("=>", Arrow),
+            (">", Greater),
+            (">=", GreaterEqual),
+            ("<", Less),
+            ("<=", LessEqual),
+            ("<<", LessLess),
+            (">>", GreaterGreater),
+            ("and", And),
+            ("class", Class),
+            ("else", Else),
+            ("false", False),
+            ("for", For),
+            ("fun", Fun),
+            ("if", If),
+            ("nil", Nil),
+            ("or", Or),
+            ("print", Print),
+            ("return", Return),
+            ("super", Super),
+            ("this", This),
+            ("true", True),
+            ("var", Var),
+            ("while", While),
+        ];
+
+        const LETTERS: &[u8] = b"abcxyzABC_";
+        const DIGITS: &[u8] = b"0123456789";
+        const STRING_CHARS: &[u8] = b"ab z\n\t/*;(){}0";
+
+        match rng.below(4) {
+            0 => {
+                let (lexeme, token_type) = rng.pick(FIXED);
+                (lexeme.to_string(), token_type)
+            }
+            1 => {
+                //a letter first, and a digit on the end so it's never a keyword
+                let name = format!(
+                    "{}{}{}",
+                    rng.pick(LETTERS) as char,
+                    rng.run(LETTERS, 6),
+                    rng.pick(DIGITS) as char
+                );
+                (name, Identifier)
+            }
+            2 => {
+                let mut number = rng.run(DIGITS, 5);
+
+                if rng.below(2) == 0 {
+                    number.push('.');
+                    number.push_str(&rng.run(DIGITS, 3));
+                }
+
+                (number, Number)
+            }
+            _ => (format!("\"{}\"", rng.run(STRING_CHARS, 8)), String),
+        }
+    }
+
+    #[test]
+    fn property_printed_tokens_scan_back() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let separators = [" ", "  ", "\t", "\n", " \n ", " /* c */ "];
+
+        for _ in 0..500 {
+            let count = rng.below(20);
+            let expected: Vec<(String, TokenType)> =
+                (0..count).map(|_| random_token(&mut rng)).collect();
+
+            let mut source = String::new();
+
+            for (lexeme, _) in &expected {
+                source.push_str(lexeme);
+                source.push_str(rng.pick(&separators));
+            }
+
+            let tokens = scan(&source);
+            let actual: Vec<(String, TokenType)> = tokens[..tokens.len() - 1]
+                .iter()
+                .map(|token| (token.lexeme.to_string(), *token.token_type))
+                .collect();
+
+            assert_eq!(actual, expected, "source: {source:?}");
+
+            //every token's span points back at its lexeme, and its line is the one it ends on
+            for token in &tokens {
+                assert_eq!(&source[token.span.start..token.span.end], &*token.lexeme);
+
+                let newlines = source[..token.span.end].matches('\n').count();
+                assert_eq!(token.line as usize, newlines + 1, "{token:?} in {source:?}");
+            }
+
+            //printing the scanned tokens and scanning them again changes nothing
+            let printed: Vec<&str> = tokens.iter().map(|token| &*token.lexeme).collect();
+            let rescanned = scan(&printed.join(" "));
+            let retyped: Vec<TokenType> = rescanned.iter().map(|token| *token.token_type).collect();
+            let types: Vec<TokenType> = tokens.iter().map(|token| *token.token_type).collect();
+
+            assert_eq!(retyped, types, "source: {source:?}");
+        }
+    }
+
+    //whatever the input, lossless mode gives back exactly the source, errors and all
+    #[test]
+    fn property_trivia_round_trips_any_input() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let alphabet = b"ab1 \n\t/*\"@#=!<.;_";
+
+        for _ in 0..500 {
+            let len = rng.below(40);
+            let source: String = (0..len).map(|_| rng.pick(alphabet) as char).collect();
+
+            let text: String = Scanner::new(&source)
+                .with_trivia()
+                .filter_map(Result::ok)
+                .map(|token| token.full_text())
+                .collect();
+
+            assert_eq!(text, source);
+        }
     }
 }
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use crate::expr::*;
+use crate::token::*;
+
+#[derive(Debug)]
+pub enum Stmt {
+    Expression(Expr),
+    //the `print` keyword, for the line of an error writing the output
+    Print(Token, Expr),
+    //var name = initializer;
+    Var(Token, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    //label, condition, body and the increment of a desugared `for`. the increment is kept
+    //apart from the body so that `continue` still runs it
+    While(Option<Token>, Expr, Box<Stmt>, Option<Expr>),
+    //the keyword and the label, if any
+    Break(Token, Option<Token>),
+    Continue(Token, Option<Token>),
+    Function(Arc<Function>),
+    //name, superclass and methods. the superclass is always a Variable
+    Class(Token, Option<Expr>, Vec<Arc<Function>>),
+    //label, the loop variables (one or two), the iterable, the body and the `in` keyword
+    ForIn(Option<Token>, Vec<Token>, Expr, Box<Stmt>, Token),
+    //the `return` keyword, for error reporting
+    Return(Token, Option<Expr>),
+    //the `throw` keyword, for the line of the error
+    Throw(Token, Expr),
+    //try body, the catch clause's variable and body, and the finally body
+    Try(Vec<Stmt>, Option<(Token, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    //`import "path" as name;`: the keyword, the path string and the name
+    Import(Token, Token, Token),
+    //a top level var, fun or class declaration that other modules can see
+    Export(Box<Stmt>),
+    //the `match` keyword, the value and each arm. the first arm that matches runs
+    Match(Token, Expr, Vec<(Pattern, Stmt)>),
+}
+
+impl Stmt {
+    //the name a declaration defines, if it is one
+    pub fn declared_name(&self) -> Option<&Token> {
+        match self {
+            Stmt::Var(name, _) | Stmt::Class(name, _, _) => Some(name),
+            Stmt::Function(function) => function.name.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+//the left hand side of a match arm
+#[derive(Debug)]
+pub enum Pattern {
+    //`_`, matches anything
+    Wildcard,
+    //a name, matches anything and binds it
+    Binding(Token),
+    //matches values equal to the literal
+    Literal(Literal),
+    //a | b | ..., matches if any of them does
+    Alternation(Vec<Pattern>),
+    //[a, b, ...], matches lists of exactly that length, element by element
+    List(Vec<Pattern>),
+    //Class{field, other: pattern}, matches instances of the class (or a subclass). a field on
+    //its own binds it, `field: pattern` matches it against the pattern. the class name is
+    //resolved like a variable
+    Instance(Token, Depth, Vec<(Token, Pattern)>),
+}
+
+impl Pattern {
+    //true if the pattern matches every value
+    pub fn is_catch_all(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Alternation(alternatives) => alternatives.iter().any(Pattern::is_catch_all),
+            _ => false,
+        }
+    }
+
+    //every name the pattern can bind
+    pub fn bindings<'a>(&'a self, names: &mut Vec<&'a Token>) {
+        match self {
+            Pattern::Binding(name) => names.push(name),
+            Pattern::Alternation(patterns) | Pattern::List(patterns) => {
+                for pattern in patterns {
+                    pattern.bindings(names);
+                }
+            }
+            Pattern::Instance(_, _, fields) => {
+                for (_, pattern) in fields {
+                    pattern.bindings(names);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+}
+
+//a function declaration or a lambda. shared between the AST and every function value
+//created from it, so it's reference counted rather than cloned
+#[derive(Debug)]
+pub struct Function {
+    //None for lambdas
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+//an interned identifier or string constant. comparing and hashing two symbols is just
+//comparing two integers, and a symbol doesn't borrow from the source it was scanned from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+//bucket i holds FIRST_BUCKET << i names, so 27 buckets cover every u32 id
+const FIRST_BUCKET: usize = 64;
+const BUCKETS: usize = 27;
+
+//one table for the whole process so symbols stay valid across threads and interpreters.
+//interned text is leaked on purpose, it lives as long as the program does
+struct Interner {
+    ids: RwLock<HashMap<&'static str, Symbol>>,
+    //append only. buckets are never moved once allocated and a slot is filled before its
+    //symbol is handed out, so reading a name back needs no lock
+    names: [OnceLock<Box<[OnceLock<&'static str>]>>; BUCKETS],
+}
+
+fn interner() -> &'static Interner {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+
+    INTERNER.get_or_init(|| Interner {
+        ids: RwLock::default(),
+        names: [const { OnceLock::new() }; BUCKETS],
+    })
+}
+
+//the bucket and the slot in it where the name of symbol `index` lives
+fn location(index: usize) -> (usize, usize) {
+    let bucket = (index / FIRST_BUCKET + 1).ilog2() as usize;
+
+    (bucket, index - FIRST_BUCKET * ((1 << bucket) - 1))
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        let interner = interner();
+
+        //most names have been seen before, so try with a shared lock first
+        if let Some(symbol) = interner
+            .ids
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(name)
+        {
+            return *symbol;
+        }
+
+        let mut ids = interner.ids.write().unwrap_or_else(|err| err.into_inner());
+
+        //another thread may have interned it between the two locks
+        if let Some(symbol) = ids.get(name) {
+            return *symbol;
+        }
+
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        let index = ids.len();
+        let (bucket, slot) = location(index);
+
+        let names = interner.names[bucket].get_or_init(|| {
+            (0..FIRST_BUCKET << bucket)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        names[slot].get_or_init(|| name);
+
+        let symbol = Symbol(index as u32);
+        ids.insert(name, symbol);
+
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let (bucket, slot) = location(self.0 as usize);
+
+        interner().names[bucket]
+            .get()
+            .and_then(|names| names[slot].get())
+            .expect("symbols are only made by intern")
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn locations_fill_each_bucket() {
+        assert_eq!(location(0), (0, 0));
+        assert_eq!(location(63), (0, 63));
+        assert_eq!(location(64), (1, 0));
+        assert_eq!(location(191), (1, 127));
+        assert_eq!(location(192), (2, 0));
+        assert_eq!(location(u32::MAX as usize).0, BUCKETS - 1);
+    }
+
+    //enough names to spill into several buckets, from several threads at once
+    #[test]
+    fn names_round_trip_across_threads() {
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        let name = format!("symbol_test_{}", (i * 7 + thread) % 600);
+                        let symbol = Symbol::intern(&name);
+
+                        assert_eq!(symbol.as_str(), name);
+                        assert_eq!(Symbol::intern(&name), symbol);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().expect("no thread panicked");
+        }
+    }
+}
@@ -1,24 +1,119 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::symbol::*;
 use crate::tokentype::*;
 
-#[derive(Debug, Clone)]
-pub struct Token<'a> {
+//tokens own their text (shared, cheap to clone) so they and anything built from them
+//can outlive the source and be sent between threads
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
     pub token_type: Box<TokenType>,
-    pub lexeme: &'a str,
-    pub literal: &'a str,
+    pub lexeme: Arc<str>,
+    pub literal: Arc<str>,
+    //interned name of an identifier, or the contents of a string literal
+    pub symbol: Option<Symbol>,
     pub line: u32,
+    pub span: Span,
+    //only filled in when the scanner runs with_trivia()
+    pub leading_trivia: Vec<Trivia>,
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+//byte offsets into the source, end exclusive
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    //the smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    //input the scanner reported an error for
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: Arc<str>,
 }
 
-impl<'a> Token<'a> {
-    pub fn new(token_type: Box<TokenType>, lexeme: &'a str, literal: &'a str, line: u32) -> Self {
+impl Trivia {
+    pub fn new(kind: TriviaKind, text: Arc<str>) -> Self {
+        Self { kind, text }
+    }
+}
+
+impl Token {
+    pub fn new(
+        token_type: Box<TokenType>,
+        lexeme: impl Into<Arc<str>>,
+        literal: impl Into<Arc<str>>,
+        line: u32,
+    ) -> Self {
+        let lexeme = lexeme.into();
+        let literal = literal.into();
+
+        let symbol = match *token_type {
+            TokenType::Identifier => Some(Symbol::intern(&lexeme)),
+            TokenType::String => Some(Symbol::intern(&literal)),
+            _ => None,
+        };
+
         Self {
             token_type,
             lexeme,
             literal,
+            symbol,
             line,
+            span: Span::default(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+
+    //the token exactly as it appeared in the source, trivia included
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+
+        for trivia in &self.leading_trivia {
+            text.push_str(&trivia.text);
         }
+
+        text.push_str(&self.lexeme);
+
+        for trivia in &self.trailing_trivia {
+            text.push_str(&trivia.text);
+        }
+
+        text
     }
+}
 
-    fn to_string(&self) -> String {
-        String::from("{self.token_type} {lexeme} {literal}")
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {} {}", self.token_type, self.lexeme, self.literal)
     }
 }
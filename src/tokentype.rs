@@ -8,38 +8,71 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    Question,
+    Colon,
     Minus,
+    MinusEqual,
+    MinusMinus,
     Plus,
+    PlusEqual,
+    PlusPlus,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
+    StarStar,
+    Percent,
+    PercentEqual,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    TildeSlash,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    //=>
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
     Identifier,
     String,
     Number,
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
+    Export,
     False,
+    Finally,
     Fun,
+    Match,
     For,
     If,
+    Import,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
     Eof,
@@ -48,7 +81,7 @@ pub enum TokenType {
 impl Deref for TokenType {
     type Target = TokenType;
 
-    fn deref<'a>(&'a self) -> &'a TokenType {
+    fn deref(&self) -> &TokenType {
         self
     }
 }
@@ -0,0 +1,384 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::class::*;
+use crate::function::*;
+use crate::iterator::*;
+use crate::lox::*;
+use crate::map::*;
+use crate::module::*;
+use crate::native::*;
+use crate::native_class::*;
+
+//a lox value at runtime. lists, maps and instances are reference values: copying one shares it
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Range(LoxRange),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    Module(Rc<LoxModule>),
+    //a rust type registered with Lox::define_class, and a value of it
+    NativeClass(Rc<LoxNativeClass>),
+    NativeInstance(Rc<LoxNativeInstance>),
+}
+
+impl Value {
+    //nil and false are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    //what the value is, as worded in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Range(_) => "range",
+            Value::Class(_) | Value::NativeClass(_) => "class",
+            Value::Instance(_) | Value::NativeInstance(_) => "instance",
+            Value::Module(_) => "module",
+        }
+    }
+}
+
+//numbers, strings, bools and nil compare by value, everything else by identity
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeClass(a), Value::NativeClass(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeInstance(a), Value::NativeInstance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut vec![])
+    }
+}
+
+impl Value {
+    //`containers` are the lists and maps being printed around this value. one that contains
+    //itself prints as `[...]` or `{...}` where it comes back round, rather than forever
+    fn write(&self, f: &mut fmt::Formatter, containers: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{b}"),
+            //f64's Display already leaves off the ".0" for whole numbers
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Function(function) => write!(f, "{function}"),
+            Value::Native(native) => write!(f, "{native}"),
+            Value::List(elements) => {
+                let id = Rc::as_ptr(elements) as *const ();
+
+                if containers.contains(&id) {
+                    return write!(f, "[...]");
+                }
+
+                containers.push(id);
+                write!(f, "[")?;
+
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    element.write(f, containers)?;
+                }
+
+                containers.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let id = Rc::as_ptr(map) as *const ();
+
+                if containers.contains(&id) {
+                    return write!(f, "{{...}}");
+                }
+
+                containers.push(id);
+                write!(f, "{{")?;
+
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}: ", key.to_value())?;
+                    value.write(f, containers)?;
+                }
+
+                containers.pop();
+                write!(f, "}}")
+            }
+            Value::Range(range) => write!(f, "{range}"),
+            Value::Class(class) => write!(f, "{class}"),
+            Value::Instance(instance) => write!(f, "{instance}"),
+            Value::Module(module) => write!(f, "{module}"),
+            Value::NativeClass(class) => write!(f, "{class}"),
+            Value::NativeInstance(instance) => write!(f, "{instance}"),
+        }
+    }
+}
+
+//conversions for rust code embedding lox. rust values go in with `into()`, and come back out
+//with `try_from()`, which fails with a LoxError::Conversion if the value is the wrong type
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Number(n.into())
+    }
+}
+
+//lox numbers are f64s, so anything past 2^53 loses precision
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(option: Option<T>) -> Self {
+        option.map_or(Value::Nil, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(elements: Vec<T>) -> Self {
+        Value::List(Rc::new(RefCell::new(
+            elements.into_iter().map(Into::into).collect(),
+        )))
+    }
+}
+
+fn conversion_error(expected: &str, value: &Value) -> LoxError {
+    LoxError::Conversion(format!("Expected a {expected}, got {}.", value.type_name()))
+}
+
+impl TryFrom<Value> for bool {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(conversion_error("bool", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(conversion_error("number", &value)),
+        }
+    }
+}
+
+//only whole numbers in i64's range convert
+impl TryFrom<Value> for i64 {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 => {
+                Ok(n as i64)
+            }
+            _ => Err(conversion_error("whole number", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(conversion_error("string", &value)),
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error = LoxError>> TryFrom<Value> for Vec<T> {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(elements) => elements.borrow().iter().cloned().map(T::try_from).collect(),
+            _ => Err(conversion_error("list", &value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(elements: Vec<Value>) -> Rc<RefCell<Vec<Value>>> {
+        Rc::new(RefCell::new(elements))
+    }
+
+    #[test]
+    fn nested_containers_print() {
+        let mut map = LoxMap::new();
+        map.insert(MapKey::String("a".into()), Value::List(list(vec![])));
+
+        let value = Value::List(list(vec![
+            Value::Number(1.0),
+            Value::List(list(vec![Value::String("x".into()), Value::Nil])),
+            Value::Map(Rc::new(RefCell::new(map))),
+        ]));
+
+        assert_eq!(value.to_string(), "[1, [x, nil], {a: []}]");
+    }
+
+    #[test]
+    fn list_containing_itself_prints() {
+        let xs = list(vec![Value::Number(1.0)]);
+        xs.borrow_mut().push(Value::List(Rc::clone(&xs)));
+
+        assert_eq!(Value::List(xs).to_string(), "[1, [...]]");
+    }
+
+    #[test]
+    fn cycle_through_a_map_prints() {
+        let map = Rc::new(RefCell::new(LoxMap::new()));
+        let xs = list(vec![Value::Map(Rc::clone(&map))]);
+        map.borrow_mut()
+            .insert(MapKey::String("xs".into()), Value::List(Rc::clone(&xs)));
+
+        assert_eq!(Value::List(Rc::clone(&xs)).to_string(), "[{xs: [...]}]");
+        assert_eq!(Value::Map(Rc::clone(&map)).to_string(), "{xs: [{...}]}");
+        //comparing is by identity, so it never looks inside
+        assert_eq!(Value::List(Rc::clone(&xs)), Value::List(xs));
+    }
+
+    //the same list twice, side by side, isn't a cycle
+    #[test]
+    fn shared_list_prints_in_full() {
+        let inner = list(vec![Value::Number(2.0)]);
+        let value = Value::List(list(vec![
+            Value::List(Rc::clone(&inner)),
+            Value::List(inner),
+        ]));
+
+        assert_eq!(value.to_string(), "[[2], [2]]");
+    }
+
+    fn round_trip<T>(value: T) -> T
+    where
+        T: Into<Value> + TryFrom<Value, Error = LoxError>,
+    {
+        T::try_from(value.into()).expect("converts back")
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        assert!(round_trip(true));
+        assert_eq!(round_trip(1.5), 1.5);
+        assert_eq!(round_trip(-42i64), -42);
+        assert_eq!(round_trip(String::from("héllo")), "héllo");
+        assert_eq!(round_trip(vec![1i64, 2, 3]), [1, 2, 3]);
+        assert_eq!(
+            round_trip(vec![vec![String::from("a")], vec![]]),
+            [vec![String::from("a")], vec![]]
+        );
+    }
+
+    #[test]
+    fn conversions_into_values() {
+        assert_eq!(Value::from(()), Value::Nil);
+        assert_eq!(Value::from(7i32), Value::Number(7.0));
+        assert_eq!(Value::from("s"), Value::String("s".into()));
+        assert_eq!(Value::from(None::<bool>), Value::Nil);
+        assert_eq!(Value::from(Some(2.5)), Value::Number(2.5));
+        assert_eq!(Value::from(vec![Some(1i64), None]).to_string(), "[1, nil]");
+    }
+
+    #[test]
+    fn failed_conversions() {
+        let message = |result: Result<i64, LoxError>| match result {
+            Err(LoxError::Conversion(message)) => message,
+            other => panic!("expected a conversion error, got {other:?}"),
+        };
+
+        assert_eq!(
+            message(i64::try_from(Value::Number(1.5))),
+            "Expected a whole number, got number."
+        );
+        assert_eq!(
+            message(i64::try_from(Value::Number(f64::INFINITY))),
+            "Expected a whole number, got number."
+        );
+        assert_eq!(
+            message(i64::try_from(Value::Nil)),
+            "Expected a whole number, got nil."
+        );
+
+        assert!(bool::try_from(Value::Nil).is_err());
+        assert!(f64::try_from(Value::String("1".into())).is_err());
+        assert!(String::try_from(Value::Number(1.0)).is_err());
+        //one bad element fails the whole list
+        assert!(Vec::<f64>::try_from(Value::from(vec![Value::Number(1.0), Value::Nil])).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use lox_interpreter_rust::golden::*;
+
+//runs every script under a directory in tests/ against the built `lox` binary. tests listed in
+//the directory's known_failures.txt are allowed to fail, but not to start passing unnoticed
+fn run(dir: &str) {
+    let lox = Path::new(env!("CARGO_BIN_EXE_lox_interpreter_rust"));
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir);
+
+    let suite = Suite::run(lox, &dir).expect("the tests can be read and run");
+
+    assert!(suite.as_expected(), "\n{}", suite.report());
+}
+
+//this implementation's own scripts
+#[test]
+fn lox_scripts() {
+    run("lox");
+}
+
+//the book's test suite, as it is upstream
+#[test]
+fn conformance() {
+    run("craftinginterpreters");
+}